@@ -0,0 +1,3 @@
+ALTER TABLE blogpostv2
+DROP COLUMN date,
+DROP COLUMN content;
\ No newline at end of file
@@ -0,0 +1,5 @@
+ALTER TABLE blogpostv2
+ADD COLUMN date DOUBLE NOT NULL,
+ADD COLUMN content TEXT NOT NULL;
+
+UPDATE blogpostv2 SET date = 0;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE blogpostv2
+MODIFY id INTEGER NOT NULL;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE blogpostv2
+MODIFY id INTEGER NOT NULL AUTO_INCREMENT;
\ No newline at end of file
@@ -0,0 +1,3 @@
+ALTER TABLE blogpostv2
+DROP COLUMN status,
+DROP COLUMN publish_at;
\ No newline at end of file
@@ -0,0 +1,3 @@
+ALTER TABLE blogpostv2
+ADD COLUMN status VARCHAR(16) NOT NULL DEFAULT 'published',
+ADD COLUMN publish_at DOUBLE;
\ No newline at end of file
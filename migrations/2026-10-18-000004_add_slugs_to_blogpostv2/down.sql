@@ -0,0 +1,4 @@
+DROP TABLE blogpostv2_old_slug;
+
+ALTER TABLE blogpostv2
+DROP COLUMN slug;
\ No newline at end of file
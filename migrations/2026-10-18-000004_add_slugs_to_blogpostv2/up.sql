@@ -0,0 +1,13 @@
+ALTER TABLE blogpostv2
+ADD COLUMN slug VARCHAR(256) NOT NULL;
+
+UPDATE blogpostv2 SET slug = CONCAT('post-', id);
+
+ALTER TABLE blogpostv2
+ADD UNIQUE INDEX blogpostv2_slug (slug);
+
+CREATE TABLE blogpostv2_old_slug (
+  slug VARCHAR(256) PRIMARY KEY,
+  post_id INTEGER NOT NULL,
+  FOREIGN KEY (post_id) REFERENCES blogpostv2 (id) ON DELETE CASCADE
+);
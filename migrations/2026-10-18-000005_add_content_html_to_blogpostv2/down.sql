@@ -0,0 +1,2 @@
+ALTER TABLE blogpostv2
+DROP COLUMN content_html;
\ No newline at end of file
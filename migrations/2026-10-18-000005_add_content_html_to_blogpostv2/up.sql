@@ -0,0 +1,2 @@
+ALTER TABLE blogpostv2
+ADD COLUMN content_html TEXT;
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE blogpostv2_revision
\ No newline at end of file
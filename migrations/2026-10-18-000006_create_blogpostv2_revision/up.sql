@@ -0,0 +1,11 @@
+CREATE TABLE blogpostv2_revision (
+  id INTEGER PRIMARY KEY AUTO_INCREMENT,
+  post_id INTEGER NOT NULL,
+  title VARCHAR(256) NOT NULL,
+  content TEXT NOT NULL,
+  created_at DOUBLE NOT NULL,
+  FOREIGN KEY (post_id) REFERENCES blogpostv2 (id) ON DELETE CASCADE
+);
+
+INSERT INTO blogpostv2_revision (post_id, title, content, created_at)
+SELECT id, title, content, date FROM blogpostv2;
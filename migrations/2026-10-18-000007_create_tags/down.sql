@@ -0,0 +1,3 @@
+DROP TABLE blogpostv2_tag;
+
+DROP TABLE tag;
\ No newline at end of file
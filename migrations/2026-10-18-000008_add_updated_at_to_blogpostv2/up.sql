@@ -0,0 +1,4 @@
+ALTER TABLE blogpostv2
+ADD COLUMN updated_at DOUBLE NOT NULL;
+
+UPDATE blogpostv2 SET updated_at = date;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE blogpostv2
+DROP COLUMN uid;
\ No newline at end of file
@@ -0,0 +1,7 @@
+ALTER TABLE blogpostv2
+ADD COLUMN uid VARCHAR(64) NOT NULL;
+
+UPDATE blogpostv2 SET uid = REPLACE(UUID(), '-', '');
+
+ALTER TABLE blogpostv2
+ADD UNIQUE INDEX blogpostv2_uid (uid);
\ No newline at end of file
@@ -0,0 +1,9 @@
+CREATE TABLE media (
+  id INTEGER AUTO_INCREMENT PRIMARY KEY,
+  filename VARCHAR(256) NOT NULL,
+  original_name VARCHAR(256) NOT NULL,
+  content_type VARCHAR(128) NOT NULL,
+  size INTEGER NOT NULL,
+  created_at DOUBLE NOT NULL,
+  UNIQUE INDEX media_filename (filename)
+);
\ No newline at end of file
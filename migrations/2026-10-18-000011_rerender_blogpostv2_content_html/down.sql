@@ -0,0 +1 @@
+UPDATE blogpostv2 SET content_html = NULL;
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- Rendered html is cached per post, and cached html from before code was
+-- highlighted would never pick it up. The server renders and stores it again
+-- for every post without any when it starts.
+UPDATE blogpostv2 SET content_html = NULL;
\ No newline at end of file
@@ -0,0 +1,3 @@
+DROP TABLE blogpostv2_series;
+
+DROP TABLE series;
\ No newline at end of file
@@ -0,0 +1,14 @@
+CREATE TABLE series (
+  id INTEGER PRIMARY KEY AUTO_INCREMENT,
+  title VARCHAR(256) NOT NULL,
+  description TEXT NOT NULL
+);
+
+CREATE TABLE blogpostv2_series (
+  post_id INTEGER PRIMARY KEY,
+  series_id INTEGER NOT NULL,
+  position INTEGER NOT NULL,
+  UNIQUE INDEX blogpostv2_series_position (series_id, position),
+  FOREIGN KEY (post_id) REFERENCES blogpostv2 (id) ON DELETE CASCADE,
+  FOREIGN KEY (series_id) REFERENCES series (id) ON DELETE CASCADE
+);
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE comment;
\ No newline at end of file
@@ -0,0 +1,11 @@
+CREATE TABLE comment (
+  id INTEGER PRIMARY KEY AUTO_INCREMENT,
+  post_id INTEGER NOT NULL,
+  author_name VARCHAR(128) NOT NULL,
+  body TEXT NOT NULL,
+  status VARCHAR(16) NOT NULL DEFAULT 'pending',
+  submitter VARCHAR(64) NOT NULL,
+  created_at DOUBLE NOT NULL,
+  INDEX comment_submitter (submitter, created_at),
+  FOREIGN KEY (post_id) REFERENCES blogpostv2 (id) ON DELETE CASCADE
+);
\ No newline at end of file
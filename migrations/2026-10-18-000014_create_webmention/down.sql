@@ -0,0 +1 @@
+DROP TABLE webmention;
\ No newline at end of file
@@ -0,0 +1,11 @@
+CREATE TABLE webmention (
+  id INTEGER PRIMARY KEY AUTO_INCREMENT,
+  post_id INTEGER NOT NULL,
+  source VARCHAR(512) NOT NULL,
+  target VARCHAR(512) NOT NULL,
+  status VARCHAR(16) NOT NULL DEFAULT 'pending',
+  created_at DOUBLE NOT NULL,
+  verified_at DOUBLE,
+  INDEX webmention_source (source(191)),
+  FOREIGN KEY (post_id) REFERENCES blogpostv2 (id) ON DELETE CASCADE
+);
\ No newline at end of file
@@ -0,0 +1,3 @@
+DROP TABLE blogpostv2_related;
+ALTER TABLE blogpostv2 DROP COLUMN reading_minutes;
+ALTER TABLE blogpostv2 DROP COLUMN word_count;
\ No newline at end of file
@@ -0,0 +1,13 @@
+-- Left NULL for posts saved before this, which are measured when the
+-- server starts
+ALTER TABLE blogpostv2 ADD COLUMN word_count INTEGER;
+ALTER TABLE blogpostv2 ADD COLUMN reading_minutes INTEGER;
+
+CREATE TABLE blogpostv2_related (
+  post_id INTEGER NOT NULL,
+  related_post_id INTEGER NOT NULL,
+  score DOUBLE NOT NULL,
+  PRIMARY KEY (post_id, related_post_id),
+  FOREIGN KEY (post_id) REFERENCES blogpostv2 (id) ON DELETE CASCADE,
+  FOREIGN KEY (related_post_id) REFERENCES blogpostv2 (id) ON DELETE CASCADE
+);
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE webmention_sent;
\ No newline at end of file
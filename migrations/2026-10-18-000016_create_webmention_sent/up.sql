@@ -0,0 +1,12 @@
+-- When the pages a post links to were last sent webmentions for it. Posts
+-- readers can already see sent theirs when they were saved.
+CREATE TABLE webmention_sent (
+  post_id INTEGER PRIMARY KEY,
+  sent_at DOUBLE NOT NULL,
+  FOREIGN KEY (post_id) REFERENCES blogpostv2 (id) ON DELETE CASCADE
+);
+
+INSERT INTO webmention_sent (post_id, sent_at)
+SELECT id, updated_at FROM blogpostv2
+WHERE status = 'published'
+  OR (status = 'scheduled' AND publish_at <= UNIX_TIMESTAMP() * 1000);
\ No newline at end of file
@@ -0,0 +1,3 @@
+ALTER TABLE webmention
+DROP INDEX webmention_submitter,
+DROP COLUMN submitter;
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- Who sent each mention, hashed the way comment submitters are, so that
+-- senders can be rate limited
+ALTER TABLE webmention
+ADD COLUMN submitter VARCHAR(64) NOT NULL DEFAULT '',
+ADD INDEX webmention_submitter (submitter, created_at);
\ No newline at end of file
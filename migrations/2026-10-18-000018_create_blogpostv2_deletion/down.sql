@@ -0,0 +1 @@
+DROP TABLE blogpostv2_deletion;
\ No newline at end of file
@@ -0,0 +1,4 @@
+CREATE TABLE blogpostv2_deletion (
+  post_id INTEGER PRIMARY KEY,
+  deleted_at DOUBLE NOT NULL
+);
\ No newline at end of file
@@ -0,0 +1,283 @@
+use crate::blogposts::v2::{self, now};
+use crate::db::{is_deadlock, last_insert_id, DEADLOCK_RETRIES};
+use crate::schema::{blogpostv2, comment};
+use diesel::deserialize::{self, FromSql};
+use diesel::mysql::{Mysql, MysqlConnection};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Varchar;
+use diesel::{Connection, ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl};
+use juniper::{GraphQLEnum, GraphQLObject};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+
+pub const MAX_NAME_LENGTH: usize = 100;
+
+pub const MAX_BODY_LENGTH: usize = 5000;
+
+/// How many comments one person can submit within `RATE_LIMIT_WINDOW`
+pub const RATE_LIMIT: usize = 3;
+
+/// Ten minutes, in milliseconds
+pub const RATE_LIMIT_WINDOW: f64 = 10.0 * 60.0 * 1000.0;
+
+#[derive(Queryable, GraphQLObject)]
+#[graphql(description = "A reader's comment on a blog post")]
+pub struct Comment {
+    pub id: i32,
+    pub post_id: i32,
+    pub author_name: String,
+    #[graphql(description = "Plain text, exactly as it was submitted")]
+    pub body: String,
+    pub status: Status,
+    #[graphql(description = "When the comment was submitted, in milliseconds since the epoch")]
+    pub created_at: f64,
+}
+
+/// Everything but the submitter, which is only for rate limiting
+type Columns = (
+    comment::id,
+    comment::post_id,
+    comment::author_name,
+    comment::body,
+    comment::status,
+    comment::created_at,
+);
+
+const COLUMNS: Columns = (
+    comment::id,
+    comment::post_id,
+    comment::author_name,
+    comment::body,
+    comment::status,
+    comment::created_at,
+);
+
+#[derive(Debug, Clone, Copy, PartialEq, AsExpression, FromSqlRow, GraphQLEnum)]
+#[sql_type = "Varchar"]
+#[graphql(description = "Whether a comment has been moderated, and how")]
+pub enum Status {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+#[derive(Insertable)]
+#[table_name = "comment"]
+pub struct New<'a> {
+    pub post_id: i32,
+    pub author_name: &'a str,
+    pub body: &'a str,
+    pub submitter: &'a str,
+}
+
+pub enum Rejection {
+    Invalid(String),
+    RateLimited,
+    Query(diesel::result::Error),
+}
+
+impl From<diesel::result::Error> for Rejection {
+    fn from(err: diesel::result::Error) -> Rejection {
+        Rejection::Query(err)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// STATUS //
+////////////////////////////////////////////////////////////////////////////////
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::Approved => "approved",
+            Status::Rejected => "rejected",
+        }
+    }
+}
+
+impl ToSql<Varchar, Mysql> for Status {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Mysql>) -> serialize::Result {
+        ToSql::<Varchar, Mysql>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Varchar, Mysql> for Status {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"pending" => Ok(Status::Pending),
+            b"approved" => Ok(Status::Approved),
+            b"rejected" => Ok(Status::Rejected),
+            unrecognized => {
+                let mut buf = String::new();
+
+                buf.push_str("Unrecognized comment status : ");
+                buf.push_str(String::from_utf8_lossy(unrecognized).as_ref());
+
+                Err(buf.into())
+            }
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// HELPERS //
+////////////////////////////////////////////////////////////////////////////////
+
+/// Who submitted a comment, as far as rate limiting is concerned. The
+/// address is hashed with a secret so it is never stored as it is.
+pub fn submitter(secret: &str, client_address: &str) -> String {
+    let mut hasher = Sha256::new();
+
+    hasher.update(secret.as_bytes());
+    hasher.update(b":");
+    hasher.update(client_address.as_bytes());
+
+    format!("{:x}", hasher.finalize())
+}
+
+fn check_length(field: &str, value: &str, max_length: usize) -> Result<(), Rejection> {
+    let length = value.trim().chars().count();
+
+    if length == 0 || length > max_length {
+        let mut buf = String::new();
+
+        buf.push_str(field);
+        buf.push_str(" must be between 1 and ");
+        buf.push_str(max_length.to_string().as_str());
+        buf.push_str(" characters");
+
+        Err(Rejection::Invalid(buf))
+    } else {
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// QUERIES //
+////////////////////////////////////////////////////////////////////////////////
+
+/// The approved comments on a post, oldest first
+pub fn approved_for_post(conn: &MysqlConnection, post_id: i32) -> QueryResult<Vec<Comment>> {
+    comment::table
+        .select(COLUMNS)
+        .filter(comment::post_id.eq(post_id))
+        .filter(comment::status.eq(Status::Approved))
+        .order((comment::created_at.asc(), comment::id.asc()))
+        .load::<Comment>(conn)
+}
+
+/// Comments for moderation, newest first
+pub fn list(conn: &MysqlConnection, status: Option<Status>) -> QueryResult<Vec<Comment>> {
+    let mut query = comment::table.select(COLUMNS).into_boxed();
+
+    if let Some(status) = status {
+        query = query.filter(comment::status.eq(status));
+    }
+
+    query
+        .order((comment::created_at.desc(), comment::id.desc()))
+        .load::<Comment>(conn)
+}
+
+pub fn get(conn: &MysqlConnection, comment_id: i32) -> QueryResult<Comment> {
+    comment::table
+        .find(comment_id)
+        .select(COLUMNS)
+        .first::<Comment>(conn)
+}
+
+/// Save a new comment for moderation, as long as it is on a post readers
+/// can see and its submitter has not commented too often lately
+pub fn submit(conn: &MysqlConnection, new_comment: &New) -> Result<Comment, Rejection> {
+    check_length("Name", new_comment.author_name, MAX_NAME_LENGTH)?;
+    check_length("Comment", new_comment.body, MAX_BODY_LENGTH)?;
+
+    let submitted_at = now();
+
+    let post_is_visible = v2::visible(submitted_at)
+        .filter(blogpostv2::id.eq(new_comment.post_id))
+        .count()
+        .get_result::<i64>(conn)?
+        > 0;
+
+    if !post_is_visible {
+        return Err(Rejection::Query(diesel::result::Error::NotFound));
+    }
+
+    let trimmed = New {
+        post_id: new_comment.post_id,
+        author_name: new_comment.author_name.trim(),
+        body: new_comment.body.trim(),
+        submitter: new_comment.submitter,
+    };
+
+    let mut retries = 0;
+
+    loop {
+        match insert_under_limit(conn, &trimmed, submitted_at) {
+            Err(Rejection::Query(err)) if is_deadlock(&err) && retries < DEADLOCK_RETRIES => {
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// The recent comments are read with a lock on their part of the index, so
+/// that comments submitted at the same moment are counted one after the
+/// other rather than all slipping under the limit together. Submitters
+/// with no recent comments lock the same gap in the index, which can
+/// deadlock, so that is worth trying again.
+fn insert_under_limit(
+    conn: &MysqlConnection,
+    new_comment: &New,
+    submitted_at: f64,
+) -> Result<Comment, Rejection> {
+    conn.transaction(|| {
+        let recent_ids = comment::table
+            .filter(comment::submitter.eq(new_comment.submitter))
+            .filter(comment::created_at.gt(submitted_at - RATE_LIMIT_WINDOW))
+            .select(comment::id)
+            .for_update()
+            .load::<i32>(conn)?;
+
+        if recent_ids.len() >= RATE_LIMIT {
+            return Err(Rejection::RateLimited);
+        }
+
+        diesel::insert_into(comment::table)
+            .values((
+                new_comment,
+                comment::status.eq(Status::Pending),
+                comment::created_at.eq(submitted_at),
+            ))
+            .execute(conn)?;
+
+        let comment_id = diesel::select(last_insert_id).first::<u64>(conn)?;
+
+        get(conn, comment_id as i32).map_err(Rejection::Query)
+    })
+}
+
+pub fn set_status(conn: &MysqlConnection, comment_id: i32, status: Status) -> QueryResult<Comment> {
+    conn.transaction(|| {
+        get(conn, comment_id)?;
+
+        diesel::update(comment::table.find(comment_id))
+            .set(comment::status.eq(status))
+            .execute(conn)?;
+
+        get(conn, comment_id)
+    })
+}
+
+pub fn delete(conn: &MysqlConnection, comment_id: i32) -> QueryResult<Comment> {
+    conn.transaction(|| {
+        let deleted = get(conn, comment_id)?;
+
+        diesel::delete(comment::table.find(comment_id)).execute(conn)?;
+
+        Ok(deleted)
+    })
+}
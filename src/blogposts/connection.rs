@@ -0,0 +1,200 @@
+use crate::blogposts::v2::Post;
+use crate::kontext::Kontext;
+use crate::schema::blogpostv2;
+use diesel::mysql::{Mysql, MysqlConnection};
+use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl};
+use juniper::GraphQLObject;
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(GraphQLObject)]
+#[graphql(
+    Context = Kontext,
+    Scalar = juniper::DefaultScalarValue,
+    description = "A page of blog posts, newest first"
+)]
+pub struct PostConnection {
+    pub edges: Vec<PostEdge>,
+    pub page_info: PageInfo,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(Context = Kontext, Scalar = juniper::DefaultScalarValue)]
+pub struct PostEdge {
+    pub cursor: String,
+    pub node: Post,
+}
+
+#[derive(GraphQLObject)]
+pub struct PageInfo {
+    pub has_next_page: bool,
+    pub has_previous_page: bool,
+    pub start_cursor: Option<String>,
+    pub end_cursor: Option<String>,
+}
+
+/// A position in the list of posts, which are ordered by date and then id
+struct Cursor {
+    date: f64,
+    id: i32,
+}
+
+pub struct Page {
+    size: i64,
+    backward: bool,
+    after: Option<Cursor>,
+    before: Option<Cursor>,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// CURSOR //
+////////////////////////////////////////////////////////////////////////////////
+
+impl Cursor {
+    fn of(post: &Post) -> Cursor {
+        Cursor {
+            date: post.date,
+            id: post.id,
+        }
+    }
+
+    fn encode(&self) -> String {
+        let mut buf = String::new();
+
+        buf.push_str(self.date.to_bits().to_string().as_str());
+        buf.push(':');
+        buf.push_str(self.id.to_string().as_str());
+
+        base64::encode(buf)
+    }
+
+    fn decode(encoded: &str) -> Option<Cursor> {
+        let bytes = base64::decode(encoded).ok()?;
+        let decoded = String::from_utf8(bytes).ok()?;
+        let mut parts = decoded.splitn(2, ':');
+
+        let date_bits = parts.next()?.parse::<u64>().ok()?;
+        let id = parts.next()?.parse::<i32>().ok()?;
+
+        Some(Cursor {
+            date: f64::from_bits(date_bits),
+            id,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// PAGE //
+////////////////////////////////////////////////////////////////////////////////
+
+impl Page {
+    pub fn new(
+        first: Option<i32>,
+        after: Option<String>,
+        last: Option<i32>,
+        before: Option<String>,
+    ) -> Result<Page, String> {
+        let decode = |maybe_cursor: Option<String>| match maybe_cursor {
+            None => Ok(None),
+            Some(encoded) => match Cursor::decode(encoded.as_str()) {
+                Some(cursor) => Ok(Some(cursor)),
+                None => {
+                    let mut buf = String::new();
+
+                    buf.push_str("Invalid cursor : ");
+                    buf.push_str(encoded.as_str());
+
+                    Err(buf)
+                }
+            },
+        };
+
+        let (size, backward) = match (first, last) {
+            (Some(_), Some(_)) => {
+                return Err("Pass either first or last, not both".to_string());
+            }
+            (Some(size), None) => (size, false),
+            (None, Some(size)) => (size, true),
+            (None, None) => (DEFAULT_PAGE_SIZE as i32, false),
+        };
+
+        if size < 0 {
+            return Err("Page size cannot be negative".to_string());
+        }
+
+        Ok(Page {
+            size: (size as i64).min(MAX_PAGE_SIZE),
+            backward,
+            after: decode(after)?,
+            before: decode(before)?,
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// QUERIES //
+////////////////////////////////////////////////////////////////////////////////
+
+/// Load one page of the posts matched by `query`
+pub fn load(
+    conn: &MysqlConnection,
+    mut query: blogpostv2::BoxedQuery<'static, Mysql>,
+    page: &Page,
+) -> QueryResult<PostConnection> {
+    if let Some(after) = &page.after {
+        query = query.filter(
+            blogpostv2::date.lt(after.date).or(blogpostv2::date
+                .eq(after.date)
+                .and(blogpostv2::id.lt(after.id))),
+        );
+    }
+
+    if let Some(before) = &page.before {
+        query = query.filter(
+            blogpostv2::date.gt(before.date).or(blogpostv2::date
+                .eq(before.date)
+                .and(blogpostv2::id.gt(before.id))),
+        );
+    }
+
+    query = if page.backward {
+        query.order((blogpostv2::date.asc(), blogpostv2::id.asc()))
+    } else {
+        query.order((blogpostv2::date.desc(), blogpostv2::id.desc()))
+    };
+
+    let mut posts = query.limit(page.size + 1).load::<Post>(conn)?;
+
+    let has_more = posts.len() as i64 > page.size;
+    posts.truncate(page.size as usize);
+
+    if page.backward {
+        posts.reverse();
+    }
+
+    let edges: Vec<PostEdge> = posts
+        .into_iter()
+        .map(|post| PostEdge {
+            cursor: Cursor::of(&post).encode(),
+            node: post,
+        })
+        .collect();
+
+    let page_info = PageInfo {
+        has_next_page: if page.backward {
+            page.before.is_some()
+        } else {
+            has_more
+        },
+        has_previous_page: if page.backward {
+            has_more
+        } else {
+            page.after.is_some()
+        },
+        start_cursor: edges.first().map(|edge| edge.cursor.clone()),
+        end_cursor: edges.last().map(|edge| edge.cursor.clone()),
+    };
+
+    Ok(PostConnection { edges, page_info })
+}
@@ -0,0 +1,551 @@
+use crate::blogposts::v2::{self, Post, Status};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use diesel::mysql::MysqlConnection;
+use diesel::QueryResult;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+////////////////////////////////////////////////////////////////////////////////
+// TYPES //
+////////////////////////////////////////////////////////////////////////////////
+
+/// A post as a Markdown file, with its metadata in front matter fenced by
+/// `---` (YAML) or `+++` (TOML)
+pub struct Document {
+    pub format: Format,
+    pub front_matter: FrontMatter,
+    pub content: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Yaml,
+    Toml,
+}
+
+pub struct FrontMatter {
+    /// The uid of the post the file belongs to. Files without one are new
+    /// posts, and get one written into them on import.
+    pub id: Option<String>,
+    pub title: String,
+    pub date: f64,
+    pub draft: bool,
+}
+
+#[derive(Deserialize)]
+struct YamlFrontMatter {
+    id: Option<serde_yaml::Value>,
+    title: String,
+    date: String,
+    #[serde(default)]
+    draft: bool,
+}
+
+#[derive(Deserialize)]
+struct TomlFrontMatter {
+    id: Option<toml::Value>,
+    title: String,
+    date: toml::Value,
+    #[serde(default)]
+    draft: bool,
+}
+
+#[derive(Serialize)]
+struct ExportedFrontMatter<'a> {
+    id: &'a str,
+    title: &'a str,
+    date: String,
+    draft: bool,
+}
+
+#[derive(Default)]
+pub struct ImportSummary {
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+}
+
+enum Outcome {
+    Created(Post),
+    Updated,
+    Unchanged,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// PARSE //
+////////////////////////////////////////////////////////////////////////////////
+
+impl Format {
+    fn fence(self) -> &'static str {
+        match self {
+            Format::Yaml => "---",
+            Format::Toml => "+++",
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Document, String> {
+    let text = text.trim_start_matches('\u{feff}');
+    let mut lines = text.split_inclusive('\n');
+
+    let format = match lines.next().map(|line| line.trim_end()) {
+        Some("---") => Format::Yaml,
+        Some("+++") => Format::Toml,
+        _ => return Err("File does not start with front matter".to_string()),
+    };
+
+    let mut front_matter_str = String::new();
+    let mut closed = false;
+
+    for line in &mut lines {
+        if line.trim_end() == format.fence() {
+            closed = true;
+            break;
+        }
+
+        front_matter_str.push_str(line);
+    }
+
+    if !closed {
+        return Err("Front matter is never closed".to_string());
+    }
+
+    let rest: String = lines.collect();
+    let content = rest
+        .strip_prefix("\r\n")
+        .or_else(|| rest.strip_prefix('\n'))
+        .unwrap_or(rest.as_str())
+        .to_string();
+
+    let front_matter = match format {
+        Format::Yaml => {
+            let raw: YamlFrontMatter =
+                serde_yaml::from_str(front_matter_str.as_str()).map_err(|err| err.to_string())?;
+
+            let id = match raw.id {
+                Some(serde_yaml::Value::String(id)) => Some(id),
+                Some(serde_yaml::Value::Number(id)) => Some(id.to_string()),
+                Some(_) => return Err("id is not a string or a number".to_string()),
+                None => None,
+            };
+
+            FrontMatter {
+                id,
+                title: raw.title,
+                date: parse_date(raw.date.as_str())?,
+                draft: raw.draft,
+            }
+        }
+        Format::Toml => {
+            let raw: TomlFrontMatter =
+                toml::from_str(front_matter_str.as_str()).map_err(|err| err.to_string())?;
+
+            let date = match raw.date {
+                toml::Value::Datetime(date_time) => date_time.to_string(),
+                toml::Value::String(date_str) => date_str,
+                _ => return Err("date is not a date".to_string()),
+            };
+
+            let id = match raw.id {
+                Some(toml::Value::String(id)) => Some(id),
+                Some(toml::Value::Integer(id)) => Some(id.to_string()),
+                Some(_) => return Err("id is not a string or a number".to_string()),
+                None => None,
+            };
+
+            FrontMatter {
+                id,
+                title: raw.title,
+                date: parse_date(date.as_str())?,
+                draft: raw.draft,
+            }
+        }
+    };
+
+    Ok(Document {
+        format,
+        front_matter,
+        content,
+    })
+}
+
+/// Milliseconds since the epoch, from an RFC 3339 date time or a plain date.
+/// Times without an offset are taken to be in UTC.
+fn parse_date(date_str: &str) -> Result<f64, String> {
+    let date_str = date_str.trim();
+
+    let date_time: Option<DateTime<Utc>> = DateTime::parse_from_rfc3339(date_str)
+        .map(|date_time| date_time.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(date_str, "%Y-%m-%dT%H:%M:%S%.f")
+                .or_else(|_| NaiveDateTime::parse_from_str(date_str, "%Y-%m-%d %H:%M:%S%.f"))
+                .ok()
+                .map(|naive| Utc.from_utc_datetime(&naive))
+        })
+        .or_else(|| {
+            NaiveDate::parse_from_str(date_str, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+                .map(|naive| Utc.from_utc_datetime(&naive))
+        });
+
+    match date_time {
+        Some(date_time) => Ok(date_time.timestamp_millis() as f64),
+        None => {
+            let mut buf = String::new();
+
+            buf.push_str("Unrecognized date : ");
+            buf.push_str(date_str);
+
+            Err(buf)
+        }
+    }
+}
+
+/// `text` with an id added to the top of its front matter
+fn with_id(text: &str, format: Format, id: &str) -> String {
+    let text = text.trim_start_matches('\u{feff}');
+    let first_line_end = text.find('\n').map(|index| index + 1).unwrap_or(text.len());
+    let (fence, rest) = text.split_at(first_line_end);
+
+    let mut buf = String::new();
+
+    buf.push_str(fence);
+
+    match format {
+        Format::Yaml => buf.push_str("id: \""),
+        Format::Toml => buf.push_str("id = \""),
+    }
+
+    buf.push_str(id);
+    buf.push_str("\"\n");
+    buf.push_str(rest);
+
+    buf
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// RENDER //
+////////////////////////////////////////////////////////////////////////////////
+
+/// The post as a Markdown file with YAML front matter
+pub fn render(post: &Post) -> Result<String, String> {
+    let front_matter = ExportedFrontMatter {
+        id: post.uid.as_str(),
+        title: post.title.as_str(),
+        date: v2::date_time(post.date).to_rfc3339_opts(SecondsFormat::Millis, true),
+        draft: post.status == Status::Draft,
+    };
+
+    let yaml = serde_yaml::to_string(&front_matter).map_err(|err| err.to_string())?;
+
+    let mut buf = String::new();
+
+    buf.push_str("---\n");
+    buf.push_str(yaml.trim_start_matches("---").trim());
+    buf.push_str("\n---\n\n");
+    buf.push_str(post.content.as_str());
+
+    Ok(buf)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// SYNC //
+////////////////////////////////////////////////////////////////////////////////
+
+/// Create or update a post for every `.md` file in `dir`. Files are matched
+/// to posts by the id in their front matter, so importing the same files
+/// twice changes nothing the second time. Nothing is imported if two files
+/// have the same id, as they would take turns overwriting the post.
+pub fn import(conn: &MysqlConnection, dir: &str) -> Result<ImportSummary, String> {
+    let mut documents: Vec<(PathBuf, String, Document)> = Vec::new();
+
+    for path in markdown_files(dir)? {
+        let text = fs::read_to_string(&path).map_err(|err| in_file(&path, err.to_string()))?;
+        let document = parse(text.as_str()).map_err(|msg| in_file(&path, msg))?;
+
+        documents.push((path, text, document));
+    }
+
+    let mut paths_by_id: HashMap<&str, &PathBuf> = HashMap::new();
+
+    for (path, _, document) in &documents {
+        if let Some(id) = &document.front_matter.id {
+            if let Some(other_path) = paths_by_id.insert(id.as_str(), path) {
+                let mut buf = String::new();
+
+                buf.push_str("Files have the same id ");
+                buf.push_str(id.as_str());
+                buf.push_str(" : ");
+                buf.push_str(other_path.to_string_lossy().as_ref());
+                buf.push_str(" and ");
+                buf.push_str(path.to_string_lossy().as_ref());
+
+                return Err(buf);
+            }
+        }
+    }
+
+    let mut summary = ImportSummary::default();
+
+    for (path, text, document) in &documents {
+        match import_document(conn, document).map_err(|err| in_file(path, err.to_string()))? {
+            Outcome::Created(post) => {
+                if document.front_matter.id.is_none() {
+                    fs::write(path, with_id(text.as_str(), document.format, &post.uid))
+                        .map_err(|err| in_file(path, err.to_string()))?;
+                }
+
+                summary.created += 1;
+            }
+            Outcome::Updated => summary.updated += 1,
+            Outcome::Unchanged => summary.unchanged += 1,
+        }
+    }
+
+    Ok(summary)
+}
+
+fn import_document(conn: &MysqlConnection, document: &Document) -> QueryResult<Outcome> {
+    let front_matter = &document.front_matter;
+
+    let existing = match &front_matter.id {
+        Some(id) => v2::find_by_uid(conn, id.as_str())?,
+        None => None,
+    };
+
+    match existing {
+        Some(post) => {
+            // Posts that are scheduled or archived count as not being drafts,
+            // so they keep their status unless the file makes them a draft
+            let status = match (front_matter.draft, post.status) {
+                (true, Status::Draft) => None,
+                (true, _) => Some(Status::Draft),
+                (false, Status::Draft) => Some(Status::Published),
+                (false, _) => None,
+            };
+
+            let changes = v2::Changes {
+                title: Some(front_matter.title.as_str()).filter(|title| *title != post.title),
+                date: Some(front_matter.date).filter(|date| *date != post.date),
+                content: Some(document.content.as_str()).filter(|content| *content != post.content),
+                status,
+                publish_at: None,
+            };
+
+            if changes.is_empty() {
+                Ok(Outcome::Unchanged)
+            } else {
+                v2::update(conn, post.id, &changes).map(|_| Outcome::Updated)
+            }
+        }
+        None => {
+            let uid = front_matter.id.clone().unwrap_or_else(v2::new_uid);
+
+            let new_post = v2::New {
+                title: front_matter.title.as_str(),
+                date: front_matter.date,
+                content: document.content.as_str(),
+                status: if front_matter.draft {
+                    Status::Draft
+                } else {
+                    Status::Published
+                },
+                publish_at: None,
+                uid: uid.as_str(),
+            };
+
+            v2::create(conn, &new_post).map(Outcome::Created)
+        }
+    }
+}
+
+/// Write every post into `dir` as `<slug>.md`, returning how many there
+/// were. Files left from before a post was renamed are removed, so that
+/// every post has one file.
+pub fn export(conn: &MysqlConnection, dir: &str) -> Result<usize, String> {
+    let posts = v2::list(conn).map_err(|err| err.to_string())?;
+
+    fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+
+    let mut exported_paths: HashMap<&str, PathBuf> = HashMap::new();
+
+    for post in &posts {
+        let mut filename = post.slug.clone();
+        filename.push_str(".md");
+
+        let path = Path::new(dir).join(filename);
+
+        fs::write(&path, render(post)?).map_err(|err| err.to_string())?;
+
+        exported_paths.insert(post.uid.as_str(), path);
+    }
+
+    for path in markdown_files(dir)? {
+        // Files that can not be read as posts are not ours to remove
+        let id = match fs::read_to_string(&path)
+            .ok()
+            .and_then(|text| parse(&text).ok())
+        {
+            Some(document) => document.front_matter.id,
+            None => continue,
+        };
+
+        let is_stale = id
+            .and_then(|id| exported_paths.get(id.as_str()))
+            .is_some_and(|exported_path| *exported_path != path);
+
+        if is_stale {
+            fs::remove_file(&path).map_err(|err| in_file(&path, err.to_string()))?;
+        }
+    }
+
+    Ok(posts.len())
+}
+
+/// The `.md` files in `dir`, sorted by name
+fn markdown_files(dir: &str) -> Result<Vec<PathBuf>, String> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|err| err.to_string())?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "md"))
+        .collect();
+
+    paths.sort();
+
+    Ok(paths)
+}
+
+fn in_file(path: &Path, msg: String) -> String {
+    let mut buf = String::new();
+
+    buf.push_str(path.to_string_lossy().as_ref());
+    buf.push_str(" : ");
+    buf.push_str(msg.as_str());
+
+    buf
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// TESTS //
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2021-03-04T05:06:07Z
+    const DATE: f64 = 1614834367000.0;
+
+    fn post(status: Status) -> Post {
+        Post {
+            id: 1,
+            title: "Rust: a \"language\"".to_string(),
+            date: DATE,
+            content: "Some *content*\n\n---\n\nAfter a rule\n".to_string(),
+            status,
+            publish_at: None,
+            slug: "rust-a-language".to_string(),
+            content_html: None,
+            updated_at: DATE,
+            uid: "abc123".to_string(),
+            word_count: None,
+            reading_minutes: None,
+        }
+    }
+
+    #[test]
+    fn parse_reads_yaml_front_matter() {
+        let document =
+            parse("---\nid: \"abc\"\ntitle: Hello\ndate: 2021-03-04T05:06:07Z\n---\n\n# Body\n")
+                .unwrap();
+
+        assert!(document.format == Format::Yaml);
+        assert_eq!(document.front_matter.id, Some("abc".to_string()));
+        assert_eq!(document.front_matter.title, "Hello");
+        assert_eq!(document.front_matter.date, DATE);
+        assert!(!document.front_matter.draft);
+        assert_eq!(document.content, "# Body\n");
+    }
+
+    #[test]
+    fn parse_reads_numeric_ids() {
+        let yaml = parse("---\nid: 12345\ntitle: Hello\ndate: 2021-03-04\n---\nBody").unwrap();
+        let toml =
+            parse("+++\nid = 12345\ntitle = \"Hello\"\ndate = 2021-03-04\n+++\nBody").unwrap();
+
+        assert_eq!(yaml.front_matter.id, Some("12345".to_string()));
+        assert_eq!(toml.front_matter.id, Some("12345".to_string()));
+    }
+
+    #[test]
+    fn parse_reads_toml_front_matter() {
+        let document = parse(
+            "+++\r\ntitle = \"Hello\"\r\ndate = 2021-03-04T05:06:07Z\r\ndraft = true\r\n+++\r\n\r\nBody",
+        )
+        .unwrap();
+
+        assert!(document.format == Format::Toml);
+        assert_eq!(document.front_matter.id, None);
+        assert_eq!(document.front_matter.date, DATE);
+        assert!(document.front_matter.draft);
+        assert_eq!(document.content, "Body");
+    }
+
+    #[test]
+    fn parse_rejects_files_without_closed_front_matter() {
+        assert!(parse("# Just markdown").is_err());
+        assert!(parse("---\ntitle: Hello\ndate: 2021-03-04\n").is_err());
+        assert!(parse("---\ndate: 2021-03-04\n---\nNo title").is_err());
+    }
+
+    #[test]
+    fn dates_are_read_with_and_without_times_and_offsets() {
+        assert_eq!(parse_date("2021-03-04T05:06:07Z"), Ok(DATE));
+        assert_eq!(parse_date("2021-03-04T07:06:07+02:00"), Ok(DATE));
+        assert_eq!(parse_date("2021-03-04 05:06:07"), Ok(DATE));
+        assert_eq!(parse_date("2021-03-04"), Ok(1614816000000.0));
+        assert!(parse_date("March 4th").is_err());
+    }
+
+    #[test]
+    fn with_id_adds_the_id_to_the_front_matter() {
+        let yaml = with_id(
+            "---\ntitle: Hello\ndate: 2021-03-04\n---\nBody",
+            Format::Yaml,
+            "xyz",
+        );
+        let toml = with_id(
+            "+++\ntitle = \"Hello\"\ndate = 2021-03-04\n+++\nBody",
+            Format::Toml,
+            "xyz",
+        );
+
+        assert_eq!(
+            parse(yaml.as_str()).unwrap().front_matter.id,
+            Some("xyz".to_string())
+        );
+        assert_eq!(
+            parse(toml.as_str()).unwrap().front_matter.id,
+            Some("xyz".to_string())
+        );
+        assert!(yaml.ends_with("---\nBody"));
+    }
+
+    #[test]
+    fn rendered_posts_parse_back_the_same() {
+        for status in [Status::Published, Status::Draft].iter() {
+            let post = post(*status);
+            let document = parse(render(&post).unwrap().as_str()).unwrap();
+
+            assert_eq!(document.front_matter.id, Some(post.uid.clone()));
+            assert_eq!(document.front_matter.title, post.title);
+            assert_eq!(document.front_matter.date, post.date);
+            assert_eq!(document.front_matter.draft, post.status == Status::Draft);
+            assert_eq!(document.content, post.content);
+        }
+    }
+}
@@ -1 +1,10 @@
+pub mod comment;
+pub mod connection;
+pub mod file;
+pub mod related;
+pub mod revision;
+pub mod series;
+pub mod slug;
+pub mod tag;
 pub mod v2;
+pub mod webmention;
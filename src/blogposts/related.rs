@@ -0,0 +1,228 @@
+use crate::blogposts::v2::{self, Post};
+use crate::markdown;
+use crate::schema::{blogpostv2, blogpostv2_related};
+use crate::search::{tokenize, TITLE_WEIGHT};
+use diesel::mysql::MysqlConnection;
+use diesel::{BoolExpressionMethods, ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl};
+use std::collections::HashMap;
+
+#[derive(Insertable)]
+#[table_name = "blogpostv2_related"]
+struct Pair {
+    post_id: i32,
+    related_post_id: i32,
+    score: f64,
+}
+
+/// How often every word appears in a post, with words in the title counting
+/// for more
+type Terms = HashMap<String, f64>;
+
+////////////////////////////////////////////////////////////////////////////////
+// QUERIES //
+////////////////////////////////////////////////////////////////////////////////
+
+/// The visible posts most like this one, most alike first
+pub fn visible_for_post(
+    conn: &MysqlConnection,
+    post_id: i32,
+    limit: usize,
+) -> QueryResult<Vec<Post>> {
+    let related_ids: Vec<i32> = blogpostv2_related::table
+        .filter(blogpostv2_related::post_id.eq(post_id))
+        .order((
+            blogpostv2_related::score.desc(),
+            blogpostv2_related::related_post_id.desc(),
+        ))
+        .select(blogpostv2_related::related_post_id)
+        .load::<i32>(conn)?;
+
+    let mut posts: HashMap<i32, Post> = v2::visible_by_ids(conn, &related_ids)?
+        .into_iter()
+        .map(|post| (post.id, post))
+        .collect();
+
+    Ok(related_ids
+        .iter()
+        .filter_map(|related_id| posts.remove(related_id))
+        .take(limit)
+        .collect())
+}
+
+/// Score the post against every other one again. How alike two posts are
+/// depends on what else has been written, but only pairs with a post that
+/// changed are scored again, which is close enough.
+pub fn refresh(conn: &MysqlConnection, post_id: i32) -> QueryResult<()> {
+    let posts = v2::list(conn)?;
+    let (terms, rarity) = weigh(&posts);
+
+    let mut pairs: Vec<Pair> = Vec::new();
+
+    if let Some(post_terms) = terms.get(&post_id) {
+        for (other_id, other_terms) in terms.iter() {
+            if *other_id == post_id {
+                continue;
+            }
+
+            let score = similarity(post_terms, other_terms, &rarity);
+
+            if score > 0.0 {
+                pairs.push(Pair {
+                    post_id,
+                    related_post_id: *other_id,
+                    score,
+                });
+                pairs.push(Pair {
+                    post_id: *other_id,
+                    related_post_id: post_id,
+                    score,
+                });
+            }
+        }
+    }
+
+    diesel::delete(
+        blogpostv2_related::table.filter(
+            blogpostv2_related::post_id
+                .eq(post_id)
+                .or(blogpostv2_related::related_post_id.eq(post_id)),
+        ),
+    )
+    .execute(conn)?;
+
+    insert(conn, &pairs)
+}
+
+/// Measure and relate the posts saved before word counts were stored, so
+/// that every post has them without having to be edited
+pub fn backfill(conn: &MysqlConnection) -> QueryResult<()> {
+    let unmeasured: Vec<Post> = blogpostv2::table
+        .filter(blogpostv2::word_count.is_null())
+        .load::<Post>(conn)?;
+
+    if unmeasured.is_empty() {
+        return Ok(());
+    }
+
+    for post in &unmeasured {
+        let (word_count, reading_minutes) = v2::reading_stats(post.content.as_str());
+
+        diesel::update(blogpostv2::table.find(post.id))
+            .set((
+                blogpostv2::word_count.eq(Some(word_count)),
+                blogpostv2::reading_minutes.eq(Some(reading_minutes)),
+            ))
+            .execute(conn)?;
+    }
+
+    refresh_all(conn)
+}
+
+/// Score every pair of posts from scratch
+fn refresh_all(conn: &MysqlConnection) -> QueryResult<()> {
+    let posts = v2::list(conn)?;
+    let (terms, rarity) = weigh(&posts);
+
+    let mut pairs: Vec<Pair> = Vec::new();
+
+    for (post_id, post_terms) in terms.iter() {
+        for (other_id, other_terms) in terms.iter() {
+            if post_id == other_id {
+                continue;
+            }
+
+            let score = similarity(post_terms, other_terms, &rarity);
+
+            if score > 0.0 {
+                pairs.push(Pair {
+                    post_id: *post_id,
+                    related_post_id: *other_id,
+                    score,
+                });
+            }
+        }
+    }
+
+    diesel::delete(blogpostv2_related::table).execute(conn)?;
+
+    insert(conn, &pairs)
+}
+
+/// In batches, to stay under the number of values MySQL takes in one query
+fn insert(conn: &MysqlConnection, pairs: &[Pair]) -> QueryResult<()> {
+    for batch in pairs.chunks(1000) {
+        diesel::insert_into(blogpostv2_related::table)
+            .values(batch)
+            .execute(conn)?;
+    }
+
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// SIMILARITY //
+////////////////////////////////////////////////////////////////////////////////
+
+/// The terms of every post, and how rare each term is across all of them.
+/// Words every post uses say nothing about which posts are alike.
+fn weigh(posts: &[Post]) -> (HashMap<i32, Terms>, HashMap<String, f64>) {
+    let mut terms: HashMap<i32, Terms> = HashMap::new();
+    let mut document_frequency: HashMap<String, f64> = HashMap::new();
+
+    for post in posts {
+        let mut post_terms: Terms = HashMap::new();
+
+        for term in tokenize(post.title.as_str()) {
+            *post_terms.entry(term).or_insert(0.0) += TITLE_WEIGHT;
+        }
+
+        for term in tokenize(markdown::plain_text(post.content.as_str()).as_str()) {
+            *post_terms.entry(term).or_insert(0.0) += 1.0;
+        }
+
+        for term in post_terms.keys() {
+            *document_frequency.entry(term.to_string()).or_insert(0.0) += 1.0;
+        }
+
+        terms.insert(post.id, post_terms);
+    }
+
+    let document_count = posts.len() as f64;
+
+    let rarity = document_frequency
+        .into_iter()
+        .map(|(term, frequency)| (term, (document_count / frequency).ln()))
+        .collect();
+
+    (terms, rarity)
+}
+
+/// The cosine similarity of two posts, from 0 for nothing in common to 1
+/// for the same words in the same proportions
+fn similarity(a: &Terms, b: &Terms, rarity: &HashMap<String, f64>) -> f64 {
+    let weight = |term: &String, count: f64| count * rarity.get(term).copied().unwrap_or(0.0);
+
+    let magnitude = |terms: &Terms| {
+        terms
+            .iter()
+            .map(|(term, count)| weight(term, *count).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    };
+
+    let dot: f64 = a
+        .iter()
+        .filter_map(|(term, count)| {
+            b.get(term)
+                .map(|other_count| weight(term, *count) * weight(term, *other_count))
+        })
+        .sum();
+
+    let magnitudes = magnitude(a) * magnitude(b);
+
+    if magnitudes > 0.0 {
+        dot / magnitudes
+    } else {
+        0.0
+    }
+}
@@ -0,0 +1,105 @@
+use crate::blogposts::v2::{now, Post};
+use crate::schema::blogpostv2_revision;
+use diesel::mysql::MysqlConnection;
+use diesel::{ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl};
+use juniper::{GraphQLEnum, GraphQLObject};
+use similar::{ChangeTag, TextDiff};
+
+#[derive(Queryable, GraphQLObject)]
+#[graphql(description = "A saved version of a blog post")]
+pub struct Revision {
+    pub id: i32,
+    pub post_id: i32,
+    pub title: String,
+    pub content: String,
+    #[graphql(description = "When this version was saved, in milliseconds since the epoch")]
+    pub created_at: f64,
+}
+
+#[derive(Insertable)]
+#[table_name = "blogpostv2_revision"]
+struct New<'a> {
+    post_id: i32,
+    title: &'a str,
+    content: &'a str,
+    created_at: f64,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(description = "The line by line changes between two revisions of a blog post")]
+pub struct Diff {
+    pub title: Vec<DiffLine>,
+    pub content: Vec<DiffLine>,
+}
+
+#[derive(GraphQLObject)]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    pub text: String,
+}
+
+#[derive(GraphQLEnum)]
+pub enum DiffLineKind {
+    Added,
+    Removed,
+    Unchanged,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// QUERIES //
+////////////////////////////////////////////////////////////////////////////////
+
+pub fn record(conn: &MysqlConnection, post: &Post) -> QueryResult<()> {
+    diesel::insert_into(blogpostv2_revision::table)
+        .values(&New {
+            post_id: post.id,
+            title: post.title.as_str(),
+            content: post.content.as_str(),
+            created_at: now(),
+        })
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Every revision of a post, newest first
+pub fn list(conn: &MysqlConnection, post_id: i32) -> QueryResult<Vec<Revision>> {
+    blogpostv2_revision::table
+        .filter(blogpostv2_revision::post_id.eq(post_id))
+        .order((
+            blogpostv2_revision::created_at.desc(),
+            blogpostv2_revision::id.desc(),
+        ))
+        .load::<Revision>(conn)
+}
+
+pub fn get(conn: &MysqlConnection, revision_id: i32) -> QueryResult<Revision> {
+    blogpostv2_revision::table
+        .find(revision_id)
+        .first::<Revision>(conn)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// DIFF //
+////////////////////////////////////////////////////////////////////////////////
+
+pub fn diff(from: &Revision, to: &Revision) -> Diff {
+    Diff {
+        title: diff_lines(from.title.as_str(), to.title.as_str()),
+        content: diff_lines(from.content.as_str(), to.content.as_str()),
+    }
+}
+
+fn diff_lines(from: &str, to: &str) -> Vec<DiffLine> {
+    TextDiff::from_lines(from, to)
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            kind: match change.tag() {
+                ChangeTag::Insert => DiffLineKind::Added,
+                ChangeTag::Delete => DiffLineKind::Removed,
+                ChangeTag::Equal => DiffLineKind::Unchanged,
+            },
+            text: change.value().trim_end_matches('\n').to_string(),
+        })
+        .collect()
+}
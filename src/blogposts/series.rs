@@ -0,0 +1,233 @@
+use crate::blogposts::v2::{self, Post};
+use crate::db::last_insert_id;
+use crate::kontext::Kontext;
+use crate::schema::{blogpostv2, blogpostv2_series, series};
+use diesel::mysql::MysqlConnection;
+use diesel::{
+    BoolExpressionMethods, Connection, ExpressionMethods, OptionalExtension, QueryDsl, QueryResult,
+    RunQueryDsl,
+};
+use juniper::FieldResult;
+use std::collections::HashMap;
+
+#[derive(Queryable)]
+pub struct Series {
+    pub id: i32,
+    pub title: String,
+    pub description: String,
+}
+
+#[juniper::object(Context = Kontext, description = "Blog posts meant to be read in order")]
+impl Series {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn title(&self) -> &str {
+        self.title.as_str()
+    }
+
+    fn description(&self) -> &str {
+        self.description.as_str()
+    }
+
+    #[graphql(description = "The published posts in the series, in reading order")]
+    fn posts(&self, ktx: &Kontext) -> FieldResult<Vec<Post>> {
+        let conn = ktx.db_pool.get()?;
+
+        Ok(visible_parts(&conn, self.id)?)
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "series"]
+pub struct New<'a> {
+    pub title: &'a str,
+    pub description: &'a str,
+}
+
+#[derive(AsChangeset)]
+#[table_name = "series"]
+pub struct Changes<'a> {
+    pub title: Option<&'a str>,
+    pub description: Option<&'a str>,
+}
+
+#[derive(Insertable)]
+#[table_name = "blogpostv2_series"]
+struct Part {
+    post_id: i32,
+    series_id: i32,
+    position: i32,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// QUERIES //
+////////////////////////////////////////////////////////////////////////////////
+
+pub fn list(conn: &MysqlConnection) -> QueryResult<Vec<Series>> {
+    series::table
+        .order(series::title.asc())
+        .load::<Series>(conn)
+}
+
+pub fn get(conn: &MysqlConnection, series_id: i32) -> QueryResult<Series> {
+    series::table.find(series_id).first::<Series>(conn)
+}
+
+/// The series a post is part of, if it is in one
+pub fn for_post(conn: &MysqlConnection, post_id: i32) -> QueryResult<Option<Series>> {
+    blogpostv2_series::table
+        .inner_join(series::table)
+        .filter(blogpostv2_series::post_id.eq(post_id))
+        .select((series::id, series::title, series::description))
+        .first::<Series>(conn)
+        .optional()
+}
+
+/// The visible posts in a series, in order. Parts that are not visible yet
+/// are left out, so readers never get sent to a post they can not see.
+pub fn visible_parts(conn: &MysqlConnection, series_id: i32) -> QueryResult<Vec<Post>> {
+    let post_ids: Vec<i32> = blogpostv2_series::table
+        .filter(blogpostv2_series::series_id.eq(series_id))
+        .order(blogpostv2_series::position.asc())
+        .select(blogpostv2_series::post_id)
+        .load::<i32>(conn)?;
+
+    let mut posts: HashMap<i32, Post> = v2::visible_by_ids(conn, &post_ids)?
+        .into_iter()
+        .map(|post| (post.id, post))
+        .collect();
+
+    Ok(post_ids
+        .iter()
+        .filter_map(|post_id| posts.remove(post_id))
+        .collect())
+}
+
+/// The visible parts before and after a post in its series
+pub fn neighbours(
+    conn: &MysqlConnection,
+    post_id: i32,
+) -> QueryResult<(Option<Post>, Option<Post>)> {
+    let series_id = blogpostv2_series::table
+        .filter(blogpostv2_series::post_id.eq(post_id))
+        .select(blogpostv2_series::series_id)
+        .first::<i32>(conn)
+        .optional()?;
+
+    let mut parts = match series_id {
+        Some(series_id) => visible_parts(conn, series_id)?,
+        None => return Ok((None, None)),
+    };
+
+    match parts.iter().position(|part| part.id == post_id) {
+        Some(index) => {
+            let next = if index + 1 < parts.len() {
+                Some(parts.remove(index + 1))
+            } else {
+                None
+            };
+
+            let previous = if index > 0 {
+                Some(parts.remove(index - 1))
+            } else {
+                None
+            };
+
+            Ok((previous, next))
+        }
+        None => Ok((None, None)),
+    }
+}
+
+pub fn create(conn: &MysqlConnection, new_series: &New) -> QueryResult<Series> {
+    conn.transaction(|| {
+        diesel::insert_into(series::table)
+            .values(new_series)
+            .execute(conn)?;
+
+        let series_id = diesel::select(last_insert_id).first::<u64>(conn)?;
+
+        get(conn, series_id as i32)
+    })
+}
+
+pub fn update(conn: &MysqlConnection, series_id: i32, changes: &Changes) -> QueryResult<Series> {
+    conn.transaction(|| {
+        let series = get(conn, series_id)?;
+
+        if changes.title.is_none() && changes.description.is_none() {
+            return Ok(series);
+        }
+
+        diesel::update(series::table.find(series_id))
+            .set(changes)
+            .execute(conn)?;
+
+        get(conn, series_id)
+    })
+}
+
+/// Delete the series. Its posts stay, just no longer in a series.
+pub fn delete(conn: &MysqlConnection, series_id: i32) -> QueryResult<Series> {
+    conn.transaction(|| {
+        let series = get(conn, series_id)?;
+
+        diesel::delete(series::table.find(series_id)).execute(conn)?;
+
+        Ok(series)
+    })
+}
+
+/// Make `post_ids` the parts of the series, in that order. Posts can only
+/// be in one series, so any of them in another series leave it.
+pub fn set_posts(conn: &MysqlConnection, series_id: i32, post_ids: &[i32]) -> QueryResult<Series> {
+    conn.transaction(|| {
+        let series = get(conn, series_id)?;
+
+        let mut unique_post_ids: Vec<i32> = Vec::new();
+
+        for post_id in post_ids {
+            if !unique_post_ids.contains(post_id) {
+                unique_post_ids.push(*post_id);
+            }
+        }
+
+        let existing_count = blogpostv2::table
+            .filter(blogpostv2::id.eq_any(&unique_post_ids))
+            .count()
+            .get_result::<i64>(conn)?;
+
+        if existing_count as usize != unique_post_ids.len() {
+            return Err(diesel::result::Error::NotFound);
+        }
+
+        diesel::delete(
+            blogpostv2_series::table.filter(
+                blogpostv2_series::series_id
+                    .eq(series_id)
+                    .or(blogpostv2_series::post_id.eq_any(&unique_post_ids)),
+            ),
+        )
+        .execute(conn)?;
+
+        let parts: Vec<Part> = unique_post_ids
+            .iter()
+            .enumerate()
+            .map(|(index, post_id)| Part {
+                post_id: *post_id,
+                series_id,
+                position: index as i32,
+            })
+            .collect();
+
+        if !parts.is_empty() {
+            diesel::insert_into(blogpostv2_series::table)
+                .values(&parts)
+                .execute(conn)?;
+        }
+
+        Ok(series)
+    })
+}
@@ -0,0 +1,107 @@
+use crate::schema::{blogpostv2, blogpostv2_old_slug};
+use diesel::mysql::MysqlConnection;
+use diesel::{ExpressionMethods, OptionalExtension, QueryDsl, QueryResult, RunQueryDsl};
+
+/// Slugs are stored in a VARCHAR(256), so leave room for a numeric suffix
+const MAX_BASE_LENGTH: usize = 200;
+
+#[derive(Insertable)]
+#[table_name = "blogpostv2_old_slug"]
+struct OldSlug<'a> {
+    slug: &'a str,
+    post_id: i32,
+}
+
+pub fn slugify(title: &str) -> String {
+    let mut buf = String::new();
+    let mut needs_dash = false;
+
+    for c in title.chars() {
+        if buf.len() >= MAX_BASE_LENGTH {
+            break;
+        }
+
+        if c.is_ascii_alphanumeric() {
+            if needs_dash && !buf.is_empty() {
+                buf.push('-');
+            }
+
+            buf.push(c.to_ascii_lowercase());
+            needs_dash = false;
+        } else {
+            needs_dash = true;
+        }
+    }
+
+    if buf.is_empty() {
+        buf.push_str("post");
+    }
+
+    buf
+}
+
+/// A slug for `title` that no other post uses now or has used before.
+/// `post_id` is the post being renamed, if any, which may reclaim its own
+/// old slugs.
+pub fn unique(conn: &MysqlConnection, title: &str, post_id: Option<i32>) -> QueryResult<String> {
+    let base = slugify(title);
+    let mut candidate = base.clone();
+    let mut suffix = 1;
+
+    while is_taken(conn, candidate.as_str(), post_id)? {
+        suffix += 1;
+
+        candidate = base.clone();
+        candidate.push('-');
+        candidate.push_str(suffix.to_string().as_str());
+    }
+
+    Ok(candidate)
+}
+
+fn is_taken(conn: &MysqlConnection, slug: &str, post_id: Option<i32>) -> QueryResult<bool> {
+    let current_owner = blogpostv2::table
+        .filter(blogpostv2::slug.eq(slug))
+        .select(blogpostv2::id)
+        .first::<i32>(conn)
+        .optional()?;
+
+    let previous_owner = blogpostv2_old_slug::table
+        .find(slug)
+        .select(blogpostv2_old_slug::post_id)
+        .first::<i32>(conn)
+        .optional()?;
+
+    let taken_by_other = |owner: Option<i32>| owner.is_some() && owner != post_id;
+
+    Ok(taken_by_other(current_owner) || taken_by_other(previous_owner))
+}
+
+/// Remember `old_slug` so links to it keep working after the post moved to
+/// `new_slug`
+pub fn record_rename(
+    conn: &MysqlConnection,
+    post_id: i32,
+    old_slug: &str,
+    new_slug: &str,
+) -> QueryResult<()> {
+    diesel::delete(blogpostv2_old_slug::table.find(new_slug)).execute(conn)?;
+
+    diesel::insert_into(blogpostv2_old_slug::table)
+        .values(&OldSlug {
+            slug: old_slug,
+            post_id,
+        })
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// The id of the post that used to be at `old_slug`
+pub fn previous_owner(conn: &MysqlConnection, old_slug: &str) -> QueryResult<Option<i32>> {
+    blogpostv2_old_slug::table
+        .find(old_slug)
+        .select(blogpostv2_old_slug::post_id)
+        .first::<i32>(conn)
+        .optional()
+}
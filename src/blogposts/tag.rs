@@ -0,0 +1,165 @@
+use crate::blogposts::v2::{now, visible};
+use crate::schema::{blogpostv2, blogpostv2_tag, tag};
+use diesel::mysql::MysqlConnection;
+use diesel::{ExpressionMethods, QueryDsl, QueryResult, RunQueryDsl};
+use juniper::GraphQLObject;
+use std::collections::BTreeMap;
+
+/// The longest tag name the tag table can hold
+pub const MAX_NAME_LENGTH: usize = 64;
+
+#[derive(GraphQLObject)]
+#[graphql(description = "A tag and how many published posts carry it")]
+pub struct TagCount {
+    pub name: String,
+    pub post_count: i32,
+}
+
+#[derive(Insertable)]
+#[table_name = "tag"]
+struct NewTag<'a> {
+    name: &'a str,
+}
+
+#[derive(Insertable)]
+#[table_name = "blogpostv2_tag"]
+struct PostTag {
+    post_id: i32,
+    tag_id: i32,
+}
+
+/// Tag names are compared case insensitively and without surrounding space
+pub fn normalize(names: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+
+    for name in names {
+        let name = name.trim().to_lowercase();
+
+        if !name.is_empty() && !normalized.contains(&name) {
+            normalized.push(name);
+        }
+    }
+
+    normalized
+}
+
+/// Why the tag names can not be saved, if they can not
+pub fn check_names(names: &[String]) -> Result<(), String> {
+    match normalize(names)
+        .iter()
+        .find(|name| name.chars().count() > MAX_NAME_LENGTH)
+    {
+        Some(name) => {
+            let mut buf = String::new();
+
+            buf.push_str("Tag \"");
+            buf.push_str(name.as_str());
+            buf.push_str("\" is longer than ");
+            buf.push_str(MAX_NAME_LENGTH.to_string().as_str());
+            buf.push_str(" characters");
+
+            Err(buf)
+        }
+        None => Ok(()),
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// QUERIES //
+////////////////////////////////////////////////////////////////////////////////
+
+pub fn for_post(conn: &MysqlConnection, post_id: i32) -> QueryResult<Vec<String>> {
+    blogpostv2_tag::table
+        .inner_join(tag::table)
+        .filter(blogpostv2_tag::post_id.eq(post_id))
+        .select(tag::name)
+        .order(tag::name.asc())
+        .load::<String>(conn)
+}
+
+/// Replace the tags on a post, creating any tags that do not exist yet
+pub fn set_for_post(conn: &MysqlConnection, post_id: i32, names: &[String]) -> QueryResult<()> {
+    let names = normalize(names);
+
+    diesel::delete(blogpostv2_tag::table.filter(blogpostv2_tag::post_id.eq(post_id)))
+        .execute(conn)?;
+
+    if names.is_empty() {
+        return Ok(());
+    }
+
+    let new_tags: Vec<NewTag> = names
+        .iter()
+        .map(|name| NewTag {
+            name: name.as_str(),
+        })
+        .collect();
+
+    diesel::insert_or_ignore_into(tag::table)
+        .values(&new_tags)
+        .execute(conn)?;
+
+    let post_tags: Vec<PostTag> = tag::table
+        .filter(tag::name.eq_any(&names))
+        .select(tag::id)
+        .load::<i32>(conn)?
+        .into_iter()
+        .map(|tag_id| PostTag { post_id, tag_id })
+        .collect();
+
+    diesel::insert_into(blogpostv2_tag::table)
+        .values(&post_tags)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+/// Every tag used by a published post, with how many published posts use it
+pub fn counts(conn: &MysqlConnection) -> QueryResult<Vec<TagCount>> {
+    let visible_post_ids = visible(now()).select(blogpostv2::id).load::<i32>(conn)?;
+
+    let tag_names = blogpostv2_tag::table
+        .inner_join(tag::table)
+        .filter(blogpostv2_tag::post_id.eq_any(visible_post_ids))
+        .select(tag::name)
+        .load::<String>(conn)?;
+
+    let mut post_counts: BTreeMap<String, i32> = BTreeMap::new();
+
+    for name in tag_names {
+        *post_counts.entry(name).or_insert(0) += 1;
+    }
+
+    Ok(post_counts
+        .into_iter()
+        .map(|(name, post_count)| TagCount { name, post_count })
+        .collect())
+}
+
+/// The ids of every post carrying the tag `name`
+pub fn post_ids(conn: &MysqlConnection, name: &str) -> QueryResult<Vec<i32>> {
+    blogpostv2_tag::table
+        .inner_join(tag::table)
+        .filter(tag::name.eq(name.trim().to_lowercase()))
+        .select(blogpostv2_tag::post_id)
+        .load::<i32>(conn)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// TESTS //
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_over_the_column_length_are_rejected() {
+        let longest = "é".repeat(MAX_NAME_LENGTH);
+        let too_long = "a".repeat(MAX_NAME_LENGTH + 1);
+
+        assert_eq!(check_names(&[longest.to_uppercase()]), Ok(()));
+        assert_eq!(check_names(&[format!("  {}  ", longest)]), Ok(()));
+        assert!(check_names(&["rust".to_string(), too_long]).is_err());
+    }
+}
@@ -1,18 +1,554 @@
-use crate::schema::blogpostv2;
-use juniper::GraphQLObject;
+use crate::blogposts::{comment, connection, related, revision, series, slug, tag, webmention};
+use crate::db::last_insert_id;
+use crate::kontext::{check_limit, Kontext};
+use crate::markdown;
+use crate::schema::{blogpostv2, blogpostv2_deletion};
+use chrono::{DateTime, TimeZone, Utc};
+use diesel::deserialize::{self, FromSql};
+use diesel::mysql::{Mysql, MysqlConnection};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Varchar;
+use diesel::{
+    BoolExpressionMethods, Connection, ExpressionMethods, OptionalExtension, QueryDsl, QueryResult,
+    RunQueryDsl,
+};
+use juniper::{FieldResult, GraphQLEnum};
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Queryable, GraphQLObject)]
-#[graphql(description = "A blog post, version 2")]
+/// A typical adult's reading speed, for estimating reading time
+pub const WORDS_PER_MINUTE: usize = 200;
+
+#[derive(Queryable)]
 pub struct Post {
     pub id: i32,
-    // pub date: f64,
     pub title: String,
-    // pub content: String,
+    pub date: f64,
+    pub content: String,
+    pub status: Status,
+    pub publish_at: Option<f64>,
+    pub slug: String,
+    pub content_html: Option<String>,
+    pub updated_at: f64,
+    pub uid: String,
+    pub word_count: Option<i32>,
+    pub reading_minutes: Option<i32>,
+}
+
+#[juniper::object(Context = Kontext, description = "A blog post, version 2")]
+impl Post {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    fn title(&self) -> &str {
+        self.title.as_str()
+    }
+
+    fn date(&self) -> f64 {
+        self.date
+    }
+
+    #[graphql(description = "The post body as it was written, in Markdown")]
+    fn content_markdown(&self) -> &str {
+        self.content.as_str()
+    }
+
+    #[graphql(description = "The post body rendered to sanitized HTML")]
+    fn content_html(&self) -> String {
+        self.html()
+    }
+
+    fn status(&self) -> Status {
+        self.status
+    }
+
+    #[graphql(
+        description = "When a scheduled post becomes public, in milliseconds since the epoch"
+    )]
+    fn publish_at(&self) -> Option<f64> {
+        self.publish_at
+    }
+
+    #[graphql(description = "Where the post lives under /blog/")]
+    fn slug(&self) -> &str {
+        self.slug.as_str()
+    }
+
+    #[graphql(description = "When the post was last edited, in milliseconds since the epoch")]
+    fn updated_at(&self) -> f64 {
+        self.updated_at
+    }
+
+    #[graphql(description = "An identifier that never changes, used to match posts with files")]
+    fn uid(&self) -> &str {
+        self.uid.as_str()
+    }
+
+    #[graphql(description = "How many words the post has, not counting markup")]
+    fn word_count(&self) -> i32 {
+        self.stats().0
+    }
+
+    #[graphql(description = "About how long the post takes to read, in whole minutes")]
+    fn reading_minutes(&self) -> i32 {
+        self.stats().1
+    }
+
+    #[graphql(description = "Published posts with the most words in common with this one")]
+    fn related_posts(&self, ktx: &Kontext, limit: Option<i32>) -> FieldResult<Vec<Post>> {
+        let limit = check_limit(limit, 5)?;
+        let conn = ktx.db_pool.get()?;
+
+        Ok(related::visible_for_post(&conn, self.id, limit)?)
+    }
+
+    fn tags(&self, ktx: &Kontext) -> FieldResult<Vec<String>> {
+        let conn = ktx.db_pool.get()?;
+
+        Ok(tag::for_post(&conn, self.id)?)
+    }
+
+    #[graphql(description = "Approved comments on the post, oldest first")]
+    fn comments(&self, ktx: &Kontext) -> FieldResult<Vec<comment::Comment>> {
+        let conn = ktx.db_pool.get()?;
+
+        Ok(comment::approved_for_post(&conn, self.id)?)
+    }
+
+    #[graphql(description = "Verified mentions of the post on other sites, oldest first")]
+    fn webmentions(&self, ktx: &Kontext) -> FieldResult<Vec<webmention::Webmention>> {
+        let conn = ktx.db_pool.get()?;
+
+        Ok(webmention::verified_for_post(&conn, self.id)?)
+    }
+
+    #[graphql(description = "The series the post is a part of, if any")]
+    fn series(&self, ktx: &Kontext) -> FieldResult<Option<series::Series>> {
+        let conn = ktx.db_pool.get()?;
+
+        Ok(series::for_post(&conn, self.id)?)
+    }
+
+    #[graphql(description = "The published part of the series that comes before this one")]
+    fn previous_part(&self, ktx: &Kontext) -> FieldResult<Option<Post>> {
+        let conn = ktx.db_pool.get()?;
+
+        Ok(series::neighbours(&conn, self.id)?.0)
+    }
+
+    #[graphql(description = "The published part of the series that comes after this one")]
+    fn next_part(&self, ktx: &Kontext) -> FieldResult<Option<Post>> {
+        let conn = ktx.db_pool.get()?;
+
+        Ok(series::neighbours(&conn, self.id)?.1)
+    }
+}
+
+impl Post {
+    /// Where the post is served, relative to the root of the site
+    pub fn path(&self) -> String {
+        let mut buf = String::new();
+
+        buf.push_str("/blog/");
+        buf.push_str(self.slug.as_str());
+
+        buf
+    }
+
+    /// Whether the public can see the post at `now`, the same way `visible`
+    /// decides it
+    pub fn is_visible(&self, now: f64) -> bool {
+        match self.status {
+            Status::Published => true,
+            Status::Scheduled => self.publish_at.is_some_and(|publish_at| publish_at <= now),
+            _ => false,
+        }
+    }
+
+    /// When readers last saw the post change: its last edit, or when it
+    /// went up if that was later
+    pub fn changed_at(&self) -> f64 {
+        let published_at = match self.status {
+            Status::Scheduled => self.publish_at.unwrap_or(0.0),
+            _ => 0.0,
+        };
+
+        self.updated_at.max(self.date).max(published_at)
+    }
+
+    /// The stored word count and reading time. Posts saved before they were
+    /// stored get measured on the fly.
+    fn stats(&self) -> (i32, i32) {
+        match (self.word_count, self.reading_minutes) {
+            (Some(word_count), Some(reading_minutes)) => (word_count, reading_minutes),
+            _ => reading_stats(self.content.as_str()),
+        }
+    }
+
+    /// The rendered content saved with this revision of the post. Posts
+    /// without it are cached when the server starts, so rendering on the
+    /// fly only happens until then.
+    pub fn html(&self) -> String {
+        match &self.content_html {
+            Some(content_html) => content_html.clone(),
+            None => markdown::to_html(self.content.as_str()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, AsExpression, FromSqlRow, GraphQLEnum)]
+#[sql_type = "Varchar"]
+#[graphql(description = "Where a blog post is in its publishing lifecycle")]
+pub enum Status {
+    Draft,
+    Scheduled,
+    Published,
+    Archived,
 }
 
 #[derive(Insertable)]
 #[table_name = "blogpostv2"]
 pub struct New<'a> {
-    pub id: i32,
     pub title: &'a str,
+    pub date: f64,
+    pub content: &'a str,
+    pub status: Status,
+    pub publish_at: Option<f64>,
+    pub uid: &'a str,
+}
+
+#[derive(AsChangeset)]
+#[table_name = "blogpostv2"]
+pub struct Changes<'a> {
+    pub title: Option<&'a str>,
+    pub date: Option<f64>,
+    pub content: Option<&'a str>,
+    pub status: Option<Status>,
+    /// `Some(None)` clears the publish time
+    pub publish_at: Option<Option<f64>>,
+}
+
+impl<'a> Changes<'a> {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.date.is_none()
+            && self.content.is_none()
+            && self.status.is_none()
+            && self.publish_at.is_none()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// STATUS //
+////////////////////////////////////////////////////////////////////////////////
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Draft => "draft",
+            Status::Scheduled => "scheduled",
+            Status::Published => "published",
+            Status::Archived => "archived",
+        }
+    }
+}
+
+impl ToSql<Varchar, Mysql> for Status {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Mysql>) -> serialize::Result {
+        ToSql::<Varchar, Mysql>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Varchar, Mysql> for Status {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"draft" => Ok(Status::Draft),
+            b"scheduled" => Ok(Status::Scheduled),
+            b"published" => Ok(Status::Published),
+            b"archived" => Ok(Status::Archived),
+            unrecognized => {
+                let mut buf = String::new();
+
+                buf.push_str("Unrecognized blog post status : ");
+                buf.push_str(String::from_utf8_lossy(unrecognized).as_ref());
+
+                Err(buf.into())
+            }
+        }
+    }
+}
+
+/// Milliseconds since the epoch, the same unit the ui sends post dates in
+pub fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as f64)
+        .unwrap_or(0.0)
+}
+
+/// How many words the content has, and how many minutes it takes to read
+/// at `WORDS_PER_MINUTE`, rounded up so that short posts take one
+pub fn reading_stats(content: &str) -> (i32, i32) {
+    let word_count = markdown::plain_text(content).split_whitespace().count();
+    let reading_minutes = word_count.div_ceil(WORDS_PER_MINUTE);
+
+    (word_count as i32, reading_minutes as i32)
+}
+
+/// A fresh identifier for a new post
+pub fn new_uid() -> String {
+    let bytes: [u8; 16] = rand::random();
+
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn date_time(millis: f64) -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(millis as i64)
+        .single()
+        .unwrap_or(DateTime::<Utc>::MIN_UTC)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// QUERIES //
+////////////////////////////////////////////////////////////////////////////////
+
+pub fn list(conn: &MysqlConnection) -> QueryResult<Vec<Post>> {
+    blogpostv2::table.load::<Post>(conn)
+}
+
+/// Posts the public can see: published ones, and scheduled ones whose
+/// publish time has passed
+pub fn visible(now: f64) -> blogpostv2::BoxedQuery<'static, Mysql> {
+    blogpostv2::table
+        .filter(
+            blogpostv2::status
+                .eq(Status::Published)
+                .or(blogpostv2::status
+                    .eq(Status::Scheduled)
+                    .and(blogpostv2::publish_at.le(now))),
+        )
+        .into_boxed()
+}
+
+fn visible_with_tag(
+    conn: &MysqlConnection,
+    tag_name: Option<&str>,
+) -> QueryResult<blogpostv2::BoxedQuery<'static, Mysql>> {
+    let mut query = visible(now());
+
+    if let Some(name) = tag_name {
+        query = query.filter(blogpostv2::id.eq_any(tag::post_ids(conn, name)?));
+    }
+
+    Ok(query)
+}
+
+/// Every visible post, newest first
+pub fn list_visible(conn: &MysqlConnection) -> QueryResult<Vec<Post>> {
+    visible(now())
+        .order((blogpostv2::date.desc(), blogpostv2::id.desc()))
+        .load::<Post>(conn)
+}
+
+/// Every visible post with the tag, or every visible post if there is
+/// no tag, newest first
+pub fn list_visible_with_tag(
+    conn: &MysqlConnection,
+    tag_name: Option<&str>,
+) -> QueryResult<Vec<Post>> {
+    visible_with_tag(conn, tag_name)?
+        .order((blogpostv2::date.desc(), blogpostv2::id.desc()))
+        .load::<Post>(conn)
+}
+
+/// The most recent visible posts, newest first
+pub fn latest_visible(conn: &MysqlConnection, limit: i64) -> QueryResult<Vec<Post>> {
+    visible(now())
+        .order((blogpostv2::date.desc(), blogpostv2::id.desc()))
+        .limit(limit)
+        .load::<Post>(conn)
+}
+
+/// The visible posts among `ids`, in no particular order
+pub fn visible_by_ids(conn: &MysqlConnection, ids: &[i32]) -> QueryResult<Vec<Post>> {
+    visible(now())
+        .filter(blogpostv2::id.eq_any(ids.to_vec()))
+        .load::<Post>(conn)
+}
+
+pub fn page_visible(
+    conn: &MysqlConnection,
+    tag_name: Option<&str>,
+    page: &connection::Page,
+) -> QueryResult<connection::PostConnection> {
+    connection::load(conn, visible_with_tag(conn, tag_name)?, page)
+}
+
+pub fn get(conn: &MysqlConnection, post_id: i32) -> QueryResult<Post> {
+    blogpostv2::table.find(post_id).first::<Post>(conn)
+}
+
+pub fn find_by_uid(conn: &MysqlConnection, post_uid: &str) -> QueryResult<Option<Post>> {
+    blogpostv2::table
+        .filter(blogpostv2::uid.eq(post_uid))
+        .first::<Post>(conn)
+        .optional()
+}
+
+/// The visible post at `post_slug`, or the one that lived there before it
+/// was renamed
+pub fn find_visible_by_slug(conn: &MysqlConnection, post_slug: &str) -> QueryResult<Post> {
+    let current = visible(now())
+        .filter(blogpostv2::slug.eq(post_slug))
+        .first::<Post>(conn)
+        .optional()?;
+
+    match current {
+        Some(post) => Ok(post),
+        None => match slug::previous_owner(conn, post_slug)? {
+            Some(post_id) => visible(now())
+                .filter(blogpostv2::id.eq(post_id))
+                .first::<Post>(conn),
+            None => Err(diesel::result::Error::NotFound),
+        },
+    }
+}
+
+pub fn create(conn: &MysqlConnection, new_post: &New) -> QueryResult<Post> {
+    conn.transaction(|| {
+        let post_slug = slug::unique(conn, new_post.title, None)?;
+        let (word_count, reading_minutes) = reading_stats(new_post.content);
+
+        diesel::insert_into(blogpostv2::table)
+            .values((
+                new_post,
+                blogpostv2::slug.eq(post_slug),
+                blogpostv2::content_html.eq(markdown::to_html(new_post.content)),
+                blogpostv2::updated_at.eq(now()),
+                blogpostv2::word_count.eq(Some(word_count)),
+                blogpostv2::reading_minutes.eq(Some(reading_minutes)),
+            ))
+            .execute(conn)?;
+
+        let post_id = diesel::select(last_insert_id).first::<u64>(conn)?;
+        let post = get(conn, post_id as i32)?;
+
+        revision::record(conn, &post)?;
+        related::refresh(conn, post.id)?;
+
+        Ok(post)
+    })
+}
+
+pub fn update(conn: &MysqlConnection, post_id: i32, changes: &Changes) -> QueryResult<Post> {
+    conn.transaction(|| {
+        let post = get(conn, post_id)?;
+
+        if changes.is_empty() {
+            return Ok(post);
+        }
+
+        diesel::update(blogpostv2::table.find(post_id))
+            .set((changes, blogpostv2::updated_at.eq(now())))
+            .execute(conn)?;
+
+        if let Some(content) = changes.content {
+            let (word_count, reading_minutes) = reading_stats(content);
+
+            diesel::update(blogpostv2::table.find(post_id))
+                .set((
+                    blogpostv2::content_html.eq(markdown::to_html(content)),
+                    blogpostv2::word_count.eq(Some(word_count)),
+                    blogpostv2::reading_minutes.eq(Some(reading_minutes)),
+                ))
+                .execute(conn)?;
+        }
+
+        if let Some(title) = changes.title {
+            if title != post.title {
+                let new_slug = slug::unique(conn, title, Some(post_id))?;
+
+                if new_slug != post.slug {
+                    slug::record_rename(conn, post_id, post.slug.as_str(), new_slug.as_str())?;
+
+                    diesel::update(blogpostv2::table.find(post_id))
+                        .set(blogpostv2::slug.eq(new_slug))
+                        .execute(conn)?;
+                }
+            }
+        }
+
+        let updated_post = get(conn, post_id)?;
+
+        if updated_post.title != post.title || updated_post.content != post.content {
+            revision::record(conn, &updated_post)?;
+            related::refresh(conn, post_id)?;
+        }
+
+        Ok(updated_post)
+    })
+}
+
+pub fn delete(conn: &MysqlConnection, post_id: i32) -> QueryResult<Post> {
+    conn.transaction(|| {
+        let post = get(conn, post_id)?;
+
+        diesel::delete(blogpostv2::table.find(post_id)).execute(conn)?;
+
+        diesel::insert_into(blogpostv2_deletion::table)
+            .values((
+                blogpostv2_deletion::post_id.eq(post_id),
+                blogpostv2_deletion::deleted_at.eq(now()),
+            ))
+            .execute(conn)?;
+
+        Ok(post)
+    })
+}
+
+/// When anything the public can see last changed, in milliseconds since the
+/// epoch. Unlike the change times of the visible posts, this moves when a
+/// post is taken down or deleted too.
+pub fn last_changed(conn: &MysqlConnection) -> QueryResult<f64> {
+    let now = now();
+
+    let edited = blogpostv2::table
+        .select(diesel::dsl::max(blogpostv2::updated_at))
+        .first::<Option<f64>>(conn)?;
+
+    let dated = blogpostv2::table
+        .filter(blogpostv2::date.le(now))
+        .select(diesel::dsl::max(blogpostv2::date))
+        .first::<Option<f64>>(conn)?;
+
+    let published = blogpostv2::table
+        .filter(blogpostv2::publish_at.le(now))
+        .select(diesel::dsl::max(blogpostv2::publish_at))
+        .first::<Option<f64>>(conn)?;
+
+    let deleted = blogpostv2_deletion::table
+        .select(diesel::dsl::max(blogpostv2_deletion::deleted_at))
+        .first::<Option<f64>>(conn)?;
+
+    Ok([edited, dated, published, deleted]
+        .iter()
+        .flatten()
+        .fold(0.0, |latest, time| latest.max(*time)))
+}
+
+/// Render and store the html of every post that has none cached, so that
+/// rendering on the fly stays the exception
+pub fn cache_missing_html(conn: &MysqlConnection) -> QueryResult<()> {
+    let uncached: Vec<Post> = blogpostv2::table
+        .filter(blogpostv2::content_html.is_null())
+        .load::<Post>(conn)?;
+
+    for post in &uncached {
+        diesel::update(blogpostv2::table.find(post.id))
+            .set(blogpostv2::content_html.eq(markdown::to_html(post.content.as_str())))
+            .execute(conn)?;
+    }
+
+    Ok(())
 }
@@ -0,0 +1,294 @@
+use crate::blogposts::v2::{self, now, Post};
+use crate::db::{is_deadlock, last_insert_id, DEADLOCK_RETRIES};
+use crate::schema::{webmention, webmention_sent};
+use diesel::deserialize::{self, FromSql};
+use diesel::mysql::{Mysql, MysqlConnection};
+use diesel::serialize::{self, Output, ToSql};
+use diesel::sql_types::Varchar;
+use diesel::{
+    Connection, ExpressionMethods, OptionalExtension, QueryDsl, QueryResult, RunQueryDsl,
+};
+use juniper::{GraphQLEnum, GraphQLObject};
+use std::collections::HashMap;
+use std::io::Write;
+
+#[derive(Queryable, GraphQLObject)]
+#[graphql(description = "A page on another site that links to a blog post")]
+pub struct Webmention {
+    pub id: i32,
+    pub post_id: i32,
+    #[graphql(description = "The page that links to the post")]
+    pub source: String,
+    #[graphql(description = "The address of the post it links to")]
+    pub target: String,
+    pub status: Status,
+    #[graphql(description = "When the mention was received, in milliseconds since the epoch")]
+    pub created_at: f64,
+    #[graphql(description = "When the source was last found linking to the post")]
+    pub verified_at: Option<f64>,
+}
+
+/// Everything but the submitter, which is only for rate limiting
+type Columns = (
+    webmention::id,
+    webmention::post_id,
+    webmention::source,
+    webmention::target,
+    webmention::status,
+    webmention::created_at,
+    webmention::verified_at,
+);
+
+const COLUMNS: Columns = (
+    webmention::id,
+    webmention::post_id,
+    webmention::source,
+    webmention::target,
+    webmention::status,
+    webmention::created_at,
+    webmention::verified_at,
+);
+
+/// How many new mentions one sender can send within `RATE_LIMIT_WINDOW`
+pub const RATE_LIMIT: usize = 20;
+
+/// Ten minutes, in milliseconds
+pub const RATE_LIMIT_WINDOW: f64 = 10.0 * 60.0 * 1000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, AsExpression, FromSqlRow, GraphQLEnum)]
+#[sql_type = "Varchar"]
+#[graphql(description = "Whether the source of a mention really links to its target")]
+pub enum Status {
+    Pending,
+    Verified,
+    Invalid,
+}
+
+#[derive(Insertable)]
+#[table_name = "webmention"]
+struct New<'a> {
+    post_id: i32,
+    source: &'a str,
+    target: &'a str,
+    status: Status,
+    created_at: f64,
+    submitter: &'a str,
+}
+
+/// What became of a mention that was sent to the endpoint
+pub enum Receipt {
+    /// The mention is waiting to be verified, and needs to be
+    Pending(i32),
+    /// The same mention was sent before and has not been verified yet
+    AlreadyPending,
+    /// The sender has sent too many new mentions lately
+    RateLimited,
+}
+
+#[derive(Insertable)]
+#[table_name = "webmention_sent"]
+struct Sent {
+    post_id: i32,
+    sent_at: f64,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// STATUS //
+////////////////////////////////////////////////////////////////////////////////
+
+impl Status {
+    fn as_str(self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::Verified => "verified",
+            Status::Invalid => "invalid",
+        }
+    }
+}
+
+impl ToSql<Varchar, Mysql> for Status {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Mysql>) -> serialize::Result {
+        ToSql::<Varchar, Mysql>::to_sql(self.as_str(), out)
+    }
+}
+
+impl FromSql<Varchar, Mysql> for Status {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"pending" => Ok(Status::Pending),
+            b"verified" => Ok(Status::Verified),
+            b"invalid" => Ok(Status::Invalid),
+            unrecognized => {
+                let mut buf = String::new();
+
+                buf.push_str("Unrecognized webmention status : ");
+                buf.push_str(String::from_utf8_lossy(unrecognized).as_ref());
+
+                Err(buf.into())
+            }
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// QUERIES //
+////////////////////////////////////////////////////////////////////////////////
+
+/// The verified mentions of a post, oldest first
+pub fn verified_for_post(conn: &MysqlConnection, post_id: i32) -> QueryResult<Vec<Webmention>> {
+    webmention::table
+        .filter(webmention::post_id.eq(post_id))
+        .filter(webmention::status.eq(Status::Verified))
+        .order((webmention::created_at.asc(), webmention::id.asc()))
+        .select(COLUMNS)
+        .load::<Webmention>(conn)
+}
+
+pub fn get(conn: &MysqlConnection, webmention_id: i32) -> QueryResult<Webmention> {
+    webmention::table
+        .find(webmention_id)
+        .select(COLUMNS)
+        .first::<Webmention>(conn)
+}
+
+/// The mentions waiting to be verified, oldest first
+pub fn pending_ids(conn: &MysqlConnection) -> QueryResult<Vec<i32>> {
+    webmention::table
+        .filter(webmention::status.eq(Status::Pending))
+        .order(webmention::id.asc())
+        .select(webmention::id)
+        .load::<i32>(conn)
+}
+
+/// Record that `source` says it links to the post, to be verified later.
+/// Sources send the same mention again when they change, so a mention that
+/// was already received goes back to pending rather than being duplicated.
+/// Only new mentions count towards the rate limit, as only they add rows.
+pub fn receive(
+    conn: &MysqlConnection,
+    post_id: i32,
+    source: &str,
+    target: &str,
+    submitter: &str,
+) -> QueryResult<Receipt> {
+    let mut retries = 0;
+
+    loop {
+        match receive_once(conn, post_id, source, target, submitter) {
+            Err(err) if is_deadlock(&err) && retries < DEADLOCK_RETRIES => retries += 1,
+            result => return result,
+        }
+    }
+}
+
+/// Recent mentions are counted under a lock, the way comments are, so that
+/// a burst of them can not slip under the limit together
+fn receive_once(
+    conn: &MysqlConnection,
+    post_id: i32,
+    source: &str,
+    target: &str,
+    submitter: &str,
+) -> QueryResult<Receipt> {
+    conn.transaction(|| {
+        let existing = webmention::table
+            .filter(webmention::source.eq(source))
+            .filter(webmention::target.eq(target))
+            .select((webmention::id, webmention::status))
+            .first::<(i32, Status)>(conn)
+            .optional()?;
+
+        match existing {
+            Some((_, Status::Pending)) => return Ok(Receipt::AlreadyPending),
+            Some((webmention_id, _)) => {
+                diesel::update(webmention::table.find(webmention_id))
+                    .set(webmention::status.eq(Status::Pending))
+                    .execute(conn)?;
+
+                return Ok(Receipt::Pending(webmention_id));
+            }
+            None => {}
+        }
+
+        let received_at = now();
+
+        let recent_ids = webmention::table
+            .filter(webmention::submitter.eq(submitter))
+            .filter(webmention::created_at.gt(received_at - RATE_LIMIT_WINDOW))
+            .select(webmention::id)
+            .for_update()
+            .load::<i32>(conn)?;
+
+        if recent_ids.len() >= RATE_LIMIT {
+            return Ok(Receipt::RateLimited);
+        }
+
+        diesel::insert_into(webmention::table)
+            .values(&New {
+                post_id,
+                source,
+                target,
+                status: Status::Pending,
+                created_at: received_at,
+                submitter,
+            })
+            .execute(conn)?;
+
+        let webmention_id = diesel::select(last_insert_id).first::<u64>(conn)?;
+
+        Ok(Receipt::Pending(webmention_id as i32))
+    })
+}
+
+/// Record whether the source was found linking to the post
+pub fn set_verified(
+    conn: &MysqlConnection,
+    webmention_id: i32,
+    is_verified: bool,
+) -> QueryResult<Webmention> {
+    conn.transaction(|| {
+        get(conn, webmention_id)?;
+
+        if is_verified {
+            diesel::update(webmention::table.find(webmention_id))
+                .set((
+                    webmention::status.eq(Status::Verified),
+                    webmention::verified_at.eq(Some(now())),
+                ))
+                .execute(conn)?;
+        } else {
+            diesel::update(webmention::table.find(webmention_id))
+                .set(webmention::status.eq(Status::Invalid))
+                .execute(conn)?;
+        }
+
+        get(conn, webmention_id)
+    })
+}
+
+/// The posts readers can see that changed since the pages they link to were
+/// last sent webmentions, including scheduled posts that went up since
+pub fn unsent_posts(conn: &MysqlConnection) -> QueryResult<Vec<Post>> {
+    let sent: HashMap<i32, f64> = webmention_sent::table
+        .load::<(i32, f64)>(conn)?
+        .into_iter()
+        .collect();
+
+    Ok(v2::visible(now())
+        .load::<Post>(conn)?
+        .into_iter()
+        .filter(|post| {
+            !sent
+                .get(&post.id)
+                .is_some_and(|sent_at| *sent_at >= post.updated_at)
+        })
+        .collect())
+}
+
+/// Record that webmentions went out for the post as it was at `sent_at`
+pub fn set_sent(conn: &MysqlConnection, post_id: i32, sent_at: f64) -> QueryResult<()> {
+    diesel::replace_into(webmention_sent::table)
+        .values(&Sent { post_id, sent_at })
+        .execute(conn)
+        .map(|_| ())
+}
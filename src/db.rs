@@ -1,5 +1,6 @@
 use diesel::mysql::MysqlConnection;
 use diesel::r2d2::ConnectionManager;
+use diesel::sql_types::{BigInt, Unsigned};
 // use r2d2_mysql::mysql::{Opts, OptsBuilder};
 // use r2d2_mysql::MysqlConnectionManager;
 
@@ -7,6 +8,16 @@ use diesel::r2d2::ConnectionManager;
 
 pub type Pool = r2d2::Pool<ConnectionManager<MysqlConnection>>;
 
+/// How many more times a transaction is tried when MySQL undoes it to get
+/// out of a deadlock
+pub const DEADLOCK_RETRIES: usize = 3;
+
+no_arg_sql_function!(
+    last_insert_id,
+    Unsigned<BigInt>,
+    "The id generated by the most recent AUTO_INCREMENT insert on this connection"
+);
+
 pub fn get_pool(db_url: String) -> Pool {
     // let opts = Opts::from_url(&db_url).unwrap();
     // let builder = OptsBuilder::from_opts(opts);
@@ -27,3 +38,14 @@ pub fn get_pool(db_url: String) -> Pool {
         .build(manager)
         .expect("Failed to create pool")
 }
+
+/// Whether MySQL undid the transaction to get out of a deadlock, in which
+/// case it can be tried again
+pub fn is_deadlock(err: &diesel::result::Error) -> bool {
+    match err {
+        diesel::result::Error::DatabaseError(_, info) => {
+            info.message().starts_with("Deadlock found")
+        }
+        _ => false,
+    }
+}
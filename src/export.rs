@@ -0,0 +1,68 @@
+use crate::blogposts::v2::Post;
+use crate::feed;
+use crate::highlight;
+use crate::page::Page;
+use crate::sitemap;
+use std::fs;
+use std::path::Path;
+
+/// Everything a static copy of the public site is made of
+pub struct Site<'a> {
+    pub site_url: &'a str,
+    pub robots_disallow: &'a [String],
+    /// The visible posts, newest first
+    pub posts: &'a [Post],
+    pub elm_js: String,
+    pub app_js: String,
+}
+
+/// Write the site into `out_dir`, one `index.html` per page so that the
+/// paths match the ones the server uses
+pub fn write(out_dir: &str, site: &Site) -> std::io::Result<()> {
+    let out_dir = Path::new(out_dir);
+
+    let write_file = |path: &str, contents: &str| -> std::io::Result<()> {
+        let file_path = out_dir.join(path.trim_start_matches('/'));
+
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(file_path, contents)
+    };
+
+    let write_page = |page: Page| -> std::io::Result<()> {
+        let mut file_path = page.path.clone();
+
+        if !file_path.ends_with('/') {
+            file_path.push('/');
+        }
+        file_path.push_str("index.html");
+
+        write_file(file_path.as_str(), page.render(site.site_url).as_str())
+    };
+
+    write_page(Page::shell("/"))?;
+    write_page(Page::blog_index(site.posts))?;
+
+    for post in site.posts {
+        write_page(Page::blogpost(post))?;
+    }
+
+    let feed_posts = &site.posts[..site.posts.len().min(feed::LENGTH as usize)];
+
+    write_file("feed.xml", feed::rss(site.site_url, feed_posts).as_str())?;
+    write_file("atom.xml", feed::atom(site.site_url, feed_posts).as_str())?;
+    write_file(
+        "sitemap.xml",
+        sitemap::sitemap(site.site_url, site.posts).as_str(),
+    )?;
+    write_file(
+        "robots.txt",
+        sitemap::robots(site.site_url, site.robots_disallow).as_str(),
+    )?;
+
+    write_file("highlight.css", highlight::css().as_str())?;
+    write_file("elm.js", site.elm_js.as_str())?;
+    write_file("app.js", site.app_js.as_str())
+}
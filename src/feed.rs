@@ -0,0 +1,149 @@
+use crate::blogposts::v2::{date_time, Post};
+
+pub const SITE_TITLE: &str = "Chadtech";
+
+/// How many of the latest posts the feeds carry
+pub const LENGTH: i64 = 20;
+
+/// When the posts last changed, in milliseconds since the epoch
+pub fn last_updated(posts: &[Post]) -> f64 {
+    posts.iter().map(Post::changed_at).fold(0.0, f64::max)
+}
+
+pub fn rss(site_url: &str, posts: &[Post]) -> String {
+    let mut buf = String::new();
+
+    buf.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    buf.push_str("<rss version=\"2.0\" xmlns:atom=\"http://www.w3.org/2005/Atom\">\n");
+    buf.push_str("<channel>\n");
+    push_element(&mut buf, "title", SITE_TITLE);
+    push_element(&mut buf, "link", url(site_url, "/blog").as_str());
+    push_element(&mut buf, "description", "The blog of Chadtech");
+    buf.push_str("<atom:link href=\"");
+    buf.push_str(escape(url(site_url, "/feed.xml").as_str()).as_str());
+    buf.push_str("\" rel=\"self\" type=\"application/rss+xml\"/>\n");
+    push_element(
+        &mut buf,
+        "lastBuildDate",
+        date_time(last_updated(posts)).to_rfc2822().as_str(),
+    );
+
+    for post in posts {
+        let permalink = url(site_url, post.path().as_str());
+
+        buf.push_str("<item>\n");
+        push_element(&mut buf, "title", post.title.as_str());
+        push_element(&mut buf, "link", permalink.as_str());
+        buf.push_str("<guid isPermaLink=\"true\">");
+        buf.push_str(escape(permalink.as_str()).as_str());
+        buf.push_str("</guid>\n");
+        push_element(
+            &mut buf,
+            "pubDate",
+            date_time(post.date).to_rfc2822().as_str(),
+        );
+        push_element(&mut buf, "description", post.html().as_str());
+        buf.push_str("</item>\n");
+    }
+
+    buf.push_str("</channel>\n");
+    buf.push_str("</rss>\n");
+
+    buf
+}
+
+pub fn atom(site_url: &str, posts: &[Post]) -> String {
+    let mut buf = String::new();
+
+    buf.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    buf.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    push_element(&mut buf, "title", SITE_TITLE);
+    push_element(&mut buf, "id", url(site_url, "/").as_str());
+    push_link(&mut buf, "alternate", url(site_url, "/blog").as_str());
+    push_link(&mut buf, "self", url(site_url, "/atom.xml").as_str());
+    push_element(
+        &mut buf,
+        "updated",
+        date_time(last_updated(posts)).to_rfc3339().as_str(),
+    );
+    buf.push_str("<author>\n");
+    push_element(&mut buf, "name", SITE_TITLE);
+    buf.push_str("</author>\n");
+
+    for post in posts {
+        let permalink = url(site_url, post.path().as_str());
+
+        buf.push_str("<entry>\n");
+        push_element(&mut buf, "title", post.title.as_str());
+        push_element(&mut buf, "id", permalink.as_str());
+        push_link(&mut buf, "alternate", permalink.as_str());
+        push_element(
+            &mut buf,
+            "published",
+            date_time(post.date).to_rfc3339().as_str(),
+        );
+        push_element(
+            &mut buf,
+            "updated",
+            date_time(post.updated_at.max(post.date))
+                .to_rfc3339()
+                .as_str(),
+        );
+        buf.push_str("<content type=\"html\">");
+        buf.push_str(escape(post.html().as_str()).as_str());
+        buf.push_str("</content>\n");
+        buf.push_str("</entry>\n");
+    }
+
+    buf.push_str("</feed>\n");
+
+    buf
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// HELPERS //
+////////////////////////////////////////////////////////////////////////////////
+
+pub fn url(site_url: &str, path: &str) -> String {
+    let mut buf = String::new();
+
+    buf.push_str(site_url);
+    buf.push_str(path);
+
+    buf
+}
+
+fn push_element(buf: &mut String, name: &str, text: &str) {
+    buf.push('<');
+    buf.push_str(name);
+    buf.push('>');
+    buf.push_str(escape(text).as_str());
+    buf.push_str("</");
+    buf.push_str(name);
+    buf.push_str(">\n");
+}
+
+fn push_link(buf: &mut String, rel: &str, href: &str) {
+    buf.push_str("<link rel=\"");
+    buf.push_str(rel);
+    buf.push_str("\" href=\"");
+    buf.push_str(escape(href).as_str());
+    buf.push_str("\"/>\n");
+}
+
+pub fn escape(text: &str) -> String {
+    let mut buf = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => buf.push_str("&amp;"),
+            '<' => buf.push_str("&lt;"),
+            '>' => buf.push_str("&gt;"),
+            '"' => buf.push_str("&quot;"),
+            '\'' => buf.push_str("&apos;"),
+            c => buf.push(c),
+        }
+    }
+
+    buf
+}
@@ -1,3 +1,5 @@
+use crate::sitemap;
+use sha2::{Digest, Sha256};
 use std::env;
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -7,11 +9,45 @@ use std::env;
 pub struct Flags {
     pub ip_address: String,
     pub admin_password: String,
+    /// Salts the hashed addresses comments are rate limited by
+    pub comment_secret: String,
+    /// The header the reverse proxy puts the address of the client in
+    pub client_address_header: Option<String>,
     pub port_number: u64,
+    pub site_url: String,
+    pub robots_disallow: Vec<String>,
     pub dev_mode: bool,
     pub show_elm_output: bool,
+    pub mode: Mode,
+    pub out_dir: String,
+    pub posts_dir: String,
+    pub media_dir: String,
+    pub image_cache_dir: String,
+    pub check_external_links: bool,
 }
 
+/// What the binary does once it has started
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Serve,
+    /// Render the public site into `out_dir` as static files, then exit
+    ExportSite,
+    /// Create or update posts from the Markdown files in `posts_dir`, then exit
+    ImportPosts,
+    /// Write every post into `posts_dir` as a Markdown file, then exit
+    ExportPosts,
+    /// Report the broken links in every post, then exit
+    CheckLinks,
+}
+
+const DEFAULT_OUT_DIR: &str = "./dist";
+
+const DEFAULT_POSTS_DIR: &str = "./posts";
+
+const DEFAULT_MEDIA_DIR: &str = "./media";
+
+const DEFAULT_IMAGE_CACHE_DIR: &str = "./image-cache";
+
 impl Flags {
     pub fn poca() -> Result<Flags, String> {
         let mut args: Vec<String> = env::args().collect();
@@ -23,12 +59,35 @@ impl Flags {
         let mut maybe_admin_password: Result<String, String> =
             Err("admin password not set".to_string());
 
+        let mut maybe_comment_secret: Option<String> = None;
+
+        let mut client_address_header: Option<String> = None;
+
         let mut maybe_port: Result<u64, String> = Err("port number not set".to_string());
 
+        let mut maybe_site_url: Option<String> = None;
+
+        let mut robots_disallow: Vec<String> = sitemap::DEFAULT_DISALLOWED
+            .iter()
+            .map(|path| path.to_string())
+            .collect();
+
         let mut dev_mode = false;
 
         let mut show_elm_output = true;
 
+        let mut mode = Mode::Serve;
+
+        let mut out_dir = DEFAULT_OUT_DIR.to_string();
+
+        let mut posts_dir = DEFAULT_POSTS_DIR.to_string();
+
+        let mut media_dir = DEFAULT_MEDIA_DIR.to_string();
+
+        let mut image_cache_dir = DEFAULT_IMAGE_CACHE_DIR.to_string();
+
+        let mut check_external_links = false;
+
         for arg in args {
             let mut dev = || {
                 maybe_ip_address = Ok("127.0.0.1".to_string());
@@ -46,6 +105,27 @@ impl Flags {
                         show_elm_output = false;
                     }
 
+                    "export-site" => {
+                        mode = Mode::ExportSite;
+                    }
+
+                    "import-posts" => {
+                        mode = Mode::ImportPosts;
+                    }
+
+                    "export-posts" => {
+                        mode = Mode::ExportPosts;
+                    }
+
+                    "check-links" => {
+                        mode = Mode::CheckLinks;
+                    }
+
+                    "check-external-links" => {
+                        mode = Mode::CheckLinks;
+                        check_external_links = true;
+                    }
+
                     arg_str => {
                         let mut buf = String::new();
 
@@ -68,6 +148,34 @@ impl Flags {
                         "admin_password" => {
                             maybe_admin_password = Ok(value.to_string());
                         }
+                        "comment_secret" => {
+                            maybe_comment_secret = Some(value.to_string());
+                        }
+                        "client_address_header" => {
+                            client_address_header = Some(value.to_string());
+                        }
+                        "site_url" => {
+                            maybe_site_url = Some(value.trim_end_matches('/').to_string());
+                        }
+                        "robots_disallow" => {
+                            robots_disallow = value
+                                .split(',')
+                                .map(|path| path.trim().to_string())
+                                .filter(|path| !path.is_empty())
+                                .collect();
+                        }
+                        "out_dir" => {
+                            out_dir = value.to_string();
+                        }
+                        "posts_dir" => {
+                            posts_dir = value.to_string();
+                        }
+                        "media_dir" => {
+                            media_dir = value.to_string();
+                        }
+                        "image_cache_dir" => {
+                            image_cache_dir = value.to_string();
+                        }
                         "port" => match value.parse::<u64>() {
                             Ok(port) => {
                                 maybe_port = Ok(port);
@@ -94,16 +202,62 @@ impl Flags {
             }
         }
 
+        // Only serving needs the server settings
+        if mode != Mode::Serve {
+            maybe_ip_address = maybe_ip_address.or_else(|_| Ok("127.0.0.1".to_string()));
+            maybe_admin_password = maybe_admin_password.or_else(|_| Ok(String::new()));
+            maybe_port = maybe_port.or(Ok(8080));
+        }
+
         let ip_address = maybe_ip_address?;
         let admin_password = maybe_admin_password?;
+        let comment_secret =
+            maybe_comment_secret.unwrap_or_else(|| derived_comment_secret(admin_password.as_str()));
         let port_number = maybe_port?;
 
+        let site_url = match maybe_site_url {
+            Some(site_url) => site_url,
+            None => {
+                if dev_mode {
+                    let mut buf = String::new();
+
+                    buf.push_str("http://localhost:");
+                    buf.push_str(port_number.to_string().as_str());
+
+                    buf
+                } else {
+                    "https://chadtech.us".to_string()
+                }
+            }
+        };
+
         Ok(Flags {
             ip_address,
             admin_password,
+            comment_secret,
+            client_address_header,
             dev_mode,
             port_number,
+            site_url,
+            robots_disallow,
             show_elm_output,
+            mode,
+            out_dir,
+            posts_dir,
+            media_dir,
+            image_cache_dir,
+            check_external_links,
         })
     }
 }
+
+/// A secret for comments when none is given, which the hashes it makes do
+/// not give the admin password away from
+fn derived_comment_secret(admin_password: &str) -> String {
+    let mut hasher = Sha256::new();
+
+    hasher.update(b"comment_secret:");
+    hasher.update(admin_password.as_bytes());
+
+    format!("{:x}", hasher.finalize())
+}
@@ -2,30 +2,280 @@ use juniper::{FieldError, FieldResult, RootNode};
 
 use crate::analytics;
 use crate::blogposts;
-use crate::db::Pool;
-use diesel::RunQueryDsl;
+use crate::images;
+use crate::kontext::{bad_input, check_limit, Kontext};
+use crate::links;
+use crate::media;
+use crate::search;
+use diesel::{Connection, RunQueryDsl};
 use rand::Rng;
+use std::collections::HashMap;
 
-pub struct Kontext {
-    pub db_pool: Pool,
-    pub password: String,
+pub const ADMIN_PASSWORD_HEADER: &str = "x-admin-password";
+
+fn blogpost_error(msg: &str, err: diesel::result::Error) -> FieldError {
+    match err {
+        diesel::result::Error::NotFound => FieldError::new(
+            "Blog post not found",
+            graphql_value!({ "code": "NOT_FOUND" }),
+        ),
+        err => {
+            let internal_error = err.to_string();
+            FieldError::new(msg, graphql_value!({ "internal_error": internal_error }))
+        }
+    }
+}
+
+fn revision_error(msg: &str, err: diesel::result::Error) -> FieldError {
+    match err {
+        diesel::result::Error::NotFound => FieldError::new(
+            "Revision not found",
+            graphql_value!({ "code": "NOT_FOUND" }),
+        ),
+        err => blogpost_error(msg, err),
+    }
 }
 
-impl juniper::Context for Kontext {}
+fn series_error(msg: &str, err: diesel::result::Error) -> FieldError {
+    match err {
+        diesel::result::Error::NotFound => FieldError::new(
+            "Series or blog post not found",
+            graphql_value!({ "code": "NOT_FOUND" }),
+        ),
+        err => blogpost_error(msg, err),
+    }
+}
+
+fn comment_error(msg: &str, err: diesel::result::Error) -> FieldError {
+    match err {
+        diesel::result::Error::NotFound => {
+            FieldError::new("Comment not found", graphql_value!({ "code": "NOT_FOUND" }))
+        }
+        err => blogpost_error(msg, err),
+    }
+}
+
+fn media_error(msg: &str, err: diesel::result::Error) -> FieldError {
+    match err {
+        diesel::result::Error::NotFound => {
+            FieldError::new("Media not found", graphql_value!({ "code": "NOT_FOUND" }))
+        }
+        err => blogpost_error(msg, err),
+    }
+}
+
+/// Posts have to know when to go up if they are scheduled. Updates are
+/// checked against what the post will be once they are saved.
+fn check_schedule(
+    status: Option<blogposts::v2::Status>,
+    publish_at: Option<f64>,
+) -> FieldResult<()> {
+    match (status, publish_at) {
+        (Some(blogposts::v2::Status::Scheduled), None) => {
+            Err(bad_input("Scheduled posts need a publish time"))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_tags(tags: Option<&[String]>) -> FieldResult<()> {
+    match tags {
+        Some(tags) => blogposts::tag::check_names(tags).map_err(|msg| bad_input(msg.as_str())),
+        None => Ok(()),
+    }
+}
 
 pub struct Query;
 
 #[juniper::object(Context = Kontext)]
 impl Query {
-    #[graphql(description = "List of all version 2 blog posts")]
-    fn blogposts_v2(ktx: &Kontext) -> FieldResult<Vec<blogposts::v2::Post>> {
-        use crate::schema::blogpostv2::dsl::*;
+    #[graphql(description = "List of the published version 2 blog posts, newest first")]
+    fn blogposts_v2(ktx: &Kontext, tag: Option<String>) -> FieldResult<Vec<blogposts::v2::Post>> {
+        let conn = ktx.db_pool.get()?;
+
+        blogposts::v2::list_visible_with_tag(&conn, tag.as_deref())
+            .map_err(|err| blogpost_error("Failed to query posts", err))
+    }
+
+    #[graphql(description = "The published version 2 blog posts, newest first, a page at a time")]
+    fn blogposts_v2_page(
+        ktx: &Kontext,
+        tag: Option<String>,
+        first: Option<i32>,
+        after: Option<String>,
+        last: Option<i32>,
+        before: Option<String>,
+    ) -> FieldResult<blogposts::connection::PostConnection> {
+        let page = blogposts::connection::Page::new(first, after, last, before)
+            .map_err(|msg| bad_input(msg.as_str()))?;
+        let conn = ktx.db_pool.get()?;
+
+        blogposts::v2::page_visible(&conn, tag.as_deref(), &page)
+            .map_err(|err| blogpost_error("Failed to query posts", err))
+    }
+
+    #[graphql(description = "A published version 2 blog post, by its current or a previous slug")]
+    fn blogpost_by_slug(ktx: &Kontext, slug: String) -> FieldResult<blogposts::v2::Post> {
+        let conn = ktx.db_pool.get()?;
+
+        blogposts::v2::find_visible_by_slug(&conn, slug.as_str())
+            .map_err(|err| blogpost_error("Failed to query post", err))
+    }
+
+    #[graphql(description = "Published version 2 blog posts matching a search, best match first")]
+    fn search_blogposts(
+        ktx: &Kontext,
+        query: String,
+        limit: Option<i32>,
+    ) -> FieldResult<Vec<search::SearchResult>> {
+        let limit = check_limit(limit, 20)?;
+        let conn = ktx.db_pool.get()?;
+
+        let index = ktx
+            .search_index
+            .get_or_build(|| blogposts::v2::list(&conn).map(|posts| search::Index::build(&posts)))
+            .map_err(|err| blogpost_error("Failed to index posts", err))?;
+
+        let hits = index.search(query.as_str());
+        let ids: Vec<i32> = hits.iter().map(|hit| hit.post_id).collect();
+
+        let mut posts: HashMap<i32, blogposts::v2::Post> =
+            blogposts::v2::visible_by_ids(&conn, &ids)
+                .map_err(|err| blogpost_error("Failed to query posts", err))?
+                .into_iter()
+                .map(|post| (post.id, post))
+                .collect();
+
+        Ok(hits
+            .into_iter()
+            .filter_map(|hit| {
+                posts.remove(&hit.post_id).map(|post| search::SearchResult {
+                    post,
+                    score: hit.score,
+                    snippet: hit.snippet,
+                })
+            })
+            .take(limit)
+            .collect())
+    }
+
+    #[graphql(description = "The dimensions of an image under ui/public, and its variants")]
+    fn image(path: String) -> FieldResult<Option<images::Image>> {
+        images::describe(path.as_str()).map_err(|msg| {
+            FieldError::new(
+                "Failed to read image",
+                graphql_value!({ "internal_error": msg }),
+            )
+        })
+    }
+
+    #[graphql(description = "Tags used by published posts, with their post counts")]
+    fn tags(ktx: &Kontext) -> FieldResult<Vec<blogposts::tag::TagCount>> {
+        let conn = ktx.db_pool.get()?;
+
+        blogposts::tag::counts(&conn).map_err(|err| blogpost_error("Failed to query tags", err))
+    }
+
+    #[graphql(description = "Every series of blog posts, by title")]
+    fn all_series(ktx: &Kontext) -> FieldResult<Vec<blogposts::series::Series>> {
+        let conn = ktx.db_pool.get()?;
+
+        blogposts::series::list(&conn).map_err(|err| series_error("Failed to query series", err))
+    }
+
+    #[graphql(description = "A single series of blog posts")]
+    fn series(ktx: &Kontext, id: i32) -> FieldResult<blogposts::series::Series> {
         let conn = ktx.db_pool.get()?;
-        let query_results = blogpostv2
-            .load::<blogposts::v2::Post>(&conn)
-            .expect("Error querying posts");
 
-        Ok(query_results)
+        blogposts::series::get(&conn, id).map_err(|err| series_error("Failed to query series", err))
+    }
+
+    #[graphql(description = "Saved versions of a version 2 blog post, newest first")]
+    fn blogpost_v2_revisions(
+        ktx: &Kontext,
+        post_id: i32,
+    ) -> FieldResult<Vec<blogposts::revision::Revision>> {
+        ktx.authorize()?;
+        let conn = ktx.db_pool.get()?;
+
+        blogposts::v2::get(&conn, post_id)
+            .and_then(|_| blogposts::revision::list(&conn, post_id))
+            .map_err(|err| blogpost_error("Failed to query revisions", err))
+    }
+
+    #[graphql(description = "The changes made between two revisions of a blog post")]
+    fn blogpost_v2_revision_diff(
+        ktx: &Kontext,
+        from_revision_id: i32,
+        to_revision_id: i32,
+    ) -> FieldResult<blogposts::revision::Diff> {
+        ktx.authorize()?;
+        let conn = ktx.db_pool.get()?;
+
+        let from = blogposts::revision::get(&conn, from_revision_id)
+            .map_err(|err| revision_error("Failed to query revision", err))?;
+        let to = blogposts::revision::get(&conn, to_revision_id)
+            .map_err(|err| revision_error("Failed to query revision", err))?;
+
+        if from.post_id != to.post_id {
+            return Err(bad_input("Revisions must belong to the same post"));
+        }
+
+        Ok(blogposts::revision::diff(&from, &to))
+    }
+
+    #[graphql(description = "List of all version 2 blog posts, whatever their status")]
+    fn all_blogposts_v2(ktx: &Kontext) -> FieldResult<Vec<blogposts::v2::Post>> {
+        ktx.authorize()?;
+        let conn = ktx.db_pool.get()?;
+
+        blogposts::v2::list(&conn).map_err(|err| blogpost_error("Failed to query posts", err))
+    }
+
+    #[graphql(description = "Comments on any post, newest first, for moderation")]
+    fn comments(
+        ktx: &Kontext,
+        status: Option<blogposts::comment::Status>,
+    ) -> FieldResult<Vec<blogposts::comment::Comment>> {
+        ktx.authorize()?;
+        let conn = ktx.db_pool.get()?;
+
+        blogposts::comment::list(&conn, status)
+            .map_err(|err| comment_error("Failed to query comments", err))
+    }
+
+    #[graphql(description = "Every uploaded file, newest first")]
+    fn media(ktx: &Kontext) -> FieldResult<Vec<media::Media>> {
+        ktx.authorize()?;
+        let conn = ktx.db_pool.get()?;
+
+        media::list(&conn).map_err(|err| media_error("Failed to query media", err))
+    }
+
+    #[graphql(
+        description = "Posts with links or images that go nowhere. Links to other sites are only followed when checkExternal is true."
+    )]
+    fn broken_links(
+        ktx: &Kontext,
+        check_external: Option<bool>,
+    ) -> FieldResult<Vec<links::PostLinkReport>> {
+        ktx.authorize()?;
+        let conn = ktx.db_pool.get()?;
+
+        links::check(
+            &conn,
+            ktx.site_url.as_str(),
+            check_external.unwrap_or(false),
+        )
+        .map_err(|err| blogpost_error("Failed to check links", err))
+    }
+
+    #[graphql(description = "A single version 2 blog post")]
+    fn blogpost_v2(ktx: &Kontext, id: i32) -> FieldResult<blogposts::v2::Post> {
+        ktx.authorize()?;
+        let conn = ktx.db_pool.get()?;
+
+        blogposts::v2::get(&conn, id).map_err(|err| blogpost_error("Failed to query post", err))
     }
 }
 
@@ -81,62 +331,268 @@ impl Mutation {
         date: f64,
         title: String,
         content: String,
+        status: Option<blogposts::v2::Status>,
+        publish_at: Option<f64>,
+        tags: Option<Vec<String>>,
     ) -> juniper::FieldResult<blogposts::v2::Post> {
-        use crate::schema::blogpostv2;
-        use diesel::sql_types::BigInt;
+        ktx.authorize()?;
+        check_schedule(status, publish_at)?;
+        check_tags(tags.as_deref())?;
         let conn = ktx.db_pool.get()?;
 
-        #[derive(QueryableByName)]
-        struct CountQuery {
-            #[sql_type = "BigInt"]
-            count: i64,
-        }
-
-        let count_query = diesel::sql_query(r#"SELECT COUNT(*) AS count FROM blogpostv2;"#)
-            .load::<CountQuery>(&conn)
-            .expect("Query failed")
-            .pop()
-            .expect("No Count Query");
+        let uid = blogposts::v2::new_uid();
 
         let new_post = blogposts::v2::New {
-            id: count_query.count as i32,
             title: title.as_str(),
+            date,
+            content: content.as_str(),
+            status: status.unwrap_or(blogposts::v2::Status::Draft),
+            publish_at,
+            uid: uid.as_str(),
         };
 
-        let insert_result = diesel::insert_into(blogpostv2::table)
-            .values(&new_post)
-            .execute(&conn);
+        conn.transaction(|| {
+            let post = blogposts::v2::create(&conn, &new_post)?;
 
-        match insert_result {
-            Ok(n) => {
-                if n == 1 {
-                    // TODO re-query post
-                    Ok(blogposts::v2::Post {
-                        id: new_post.id,
-                        title: new_post.title.to_string(),
-                    })
-                } else {
-                    let mut buf = String::new();
-
-                    buf.push_str("Inserted ");
-                    buf.push_str(n.to_string().as_str());
-                    buf.push_str(" rows");
-
-                    Err(FieldError::new(
-                        "Failed to create new user",
-                        graphql_value!({ "internal_error": buf }),
-                    ))
-                }
+            if let Some(tags) = &tags {
+                blogposts::tag::set_for_post(&conn, post.id, tags)?;
             }
-            Err(err) => {
-                let msg = err.to_string();
-                Err(FieldError::new(
-                    "Failed to create new user",
-                    graphql_value!({ "internal_error": msg }),
-                ))
+
+            Ok(post)
+        })
+        .map_err(|err| blogpost_error("Failed to create blog post", err))
+        .inspect(|post| {
+            ktx.search_index.invalidate();
+            ktx.send_webmentions(post);
+        })
+    }
+
+    fn update_blogpost_v2(
+        ktx: &Kontext,
+        id: i32,
+        date: Option<f64>,
+        title: Option<String>,
+        content: Option<String>,
+        status: Option<blogposts::v2::Status>,
+        publish_at: Option<f64>,
+        clear_publish_at: Option<bool>,
+        tags: Option<Vec<String>>,
+    ) -> juniper::FieldResult<blogposts::v2::Post> {
+        ktx.authorize()?;
+        check_tags(tags.as_deref())?;
+        let conn = ktx.db_pool.get()?;
+
+        let publish_at = match (publish_at, clear_publish_at.unwrap_or(false)) {
+            (Some(_), true) => {
+                return Err(bad_input("publishAt can not be set and cleared at once"))
+            }
+            (_, true) => Some(None),
+            (publish_at, false) => publish_at.map(Some),
+        };
+
+        let stored = blogposts::v2::get(&conn, id)
+            .map_err(|err| blogpost_error("Failed to query blog post", err))?;
+
+        check_schedule(
+            Some(status.unwrap_or(stored.status)),
+            publish_at.unwrap_or(stored.publish_at),
+        )?;
+
+        let changes = blogposts::v2::Changes {
+            title: title.as_deref(),
+            date,
+            content: content.as_deref(),
+            status,
+            publish_at,
+        };
+
+        conn.transaction(|| {
+            let post = blogposts::v2::update(&conn, id, &changes)?;
+
+            if let Some(tags) = &tags {
+                blogposts::tag::set_for_post(&conn, post.id, tags)?;
+            }
+
+            Ok(post)
+        })
+        .map_err(|err| blogpost_error("Failed to update blog post", err))
+        .inspect(|post| {
+            ktx.search_index.invalidate();
+            ktx.send_webmentions(post);
+        })
+    }
+
+    #[graphql(description = "Make an older revision the current version of its blog post")]
+    fn restore_blogpost_v2_revision(
+        ktx: &Kontext,
+        revision_id: i32,
+    ) -> juniper::FieldResult<blogposts::v2::Post> {
+        ktx.authorize()?;
+        let conn = ktx.db_pool.get()?;
+
+        let revision = blogposts::revision::get(&conn, revision_id)
+            .map_err(|err| revision_error("Failed to query revision", err))?;
+
+        let changes = blogposts::v2::Changes {
+            title: Some(revision.title.as_str()),
+            date: None,
+            content: Some(revision.content.as_str()),
+            status: None,
+            publish_at: None,
+        };
+
+        blogposts::v2::update(&conn, revision.post_id, &changes)
+            .map_err(|err| blogpost_error("Failed to restore revision", err))
+            .inspect(|post| {
+                ktx.search_index.invalidate();
+                ktx.send_webmentions(post);
+            })
+    }
+
+    fn delete_blogpost_v2(ktx: &Kontext, id: i32) -> juniper::FieldResult<blogposts::v2::Post> {
+        ktx.authorize()?;
+        let conn = ktx.db_pool.get()?;
+
+        blogposts::v2::delete(&conn, id)
+            .map_err(|err| blogpost_error("Failed to delete blog post", err))
+            .inspect(|_| ktx.search_index.invalidate())
+    }
+
+    fn create_series(
+        ktx: &Kontext,
+        title: String,
+        description: Option<String>,
+    ) -> juniper::FieldResult<blogposts::series::Series> {
+        ktx.authorize()?;
+        let conn = ktx.db_pool.get()?;
+
+        let new_series = blogposts::series::New {
+            title: title.as_str(),
+            description: description.as_deref().unwrap_or(""),
+        };
+
+        blogposts::series::create(&conn, &new_series)
+            .map_err(|err| series_error("Failed to create series", err))
+    }
+
+    fn update_series(
+        ktx: &Kontext,
+        id: i32,
+        title: Option<String>,
+        description: Option<String>,
+    ) -> juniper::FieldResult<blogposts::series::Series> {
+        ktx.authorize()?;
+        let conn = ktx.db_pool.get()?;
+
+        let changes = blogposts::series::Changes {
+            title: title.as_deref(),
+            description: description.as_deref(),
+        };
+
+        blogposts::series::update(&conn, id, &changes)
+            .map_err(|err| series_error("Failed to update series", err))
+    }
+
+    #[graphql(description = "Delete a series, leaving its posts as they are")]
+    fn delete_series(ktx: &Kontext, id: i32) -> juniper::FieldResult<blogposts::series::Series> {
+        ktx.authorize()?;
+        let conn = ktx.db_pool.get()?;
+
+        blogposts::series::delete(&conn, id)
+            .map_err(|err| series_error("Failed to delete series", err))
+    }
+
+    #[graphql(description = "Set which posts make up a series, in reading order")]
+    fn set_series_posts(
+        ktx: &Kontext,
+        id: i32,
+        post_ids: Vec<i32>,
+    ) -> juniper::FieldResult<blogposts::series::Series> {
+        ktx.authorize()?;
+        let conn = ktx.db_pool.get()?;
+
+        blogposts::series::set_posts(&conn, id, &post_ids)
+            .map_err(|err| series_error("Failed to set the posts in series", err))
+    }
+
+    #[graphql(
+        description = "Comment on a published post. Comments are hidden until approved. The website field is for bots only, and should be left empty."
+    )]
+    fn submit_comment(
+        ktx: &Kontext,
+        post_id: i32,
+        author_name: String,
+        body: String,
+        website: Option<String>,
+    ) -> juniper::FieldResult<bool> {
+        // Real readers never see the website field, so anything filling it in
+        // is a bot. It is told the comment went through, so it does not adapt.
+        if website.is_some_and(|website| !website.trim().is_empty()) {
+            return Ok(true);
+        }
+
+        let conn = ktx.db_pool.get()?;
+
+        let submitter = blogposts::comment::submitter(
+            ktx.comment_secret.as_str(),
+            ktx.client_address.as_deref().unwrap_or(""),
+        );
+
+        let new_comment = blogposts::comment::New {
+            post_id,
+            author_name: author_name.as_str(),
+            body: body.as_str(),
+            submitter: submitter.as_str(),
+        };
+
+        match blogposts::comment::submit(&conn, &new_comment) {
+            Ok(_) => Ok(true),
+            Err(blogposts::comment::Rejection::Invalid(msg)) => Err(bad_input(msg.as_str())),
+            Err(blogposts::comment::Rejection::RateLimited) => Err(FieldError::new(
+                "Too many comments, try again later",
+                graphql_value!({ "code": "RATE_LIMITED" }),
+            )),
+            Err(blogposts::comment::Rejection::Query(err)) => {
+                Err(blogpost_error("Failed to submit comment", err))
             }
         }
     }
+
+    fn approve_comment(
+        ktx: &Kontext,
+        id: i32,
+    ) -> juniper::FieldResult<blogposts::comment::Comment> {
+        ktx.authorize()?;
+        let conn = ktx.db_pool.get()?;
+
+        blogposts::comment::set_status(&conn, id, blogposts::comment::Status::Approved)
+            .map_err(|err| comment_error("Failed to approve comment", err))
+    }
+
+    fn reject_comment(ktx: &Kontext, id: i32) -> juniper::FieldResult<blogposts::comment::Comment> {
+        ktx.authorize()?;
+        let conn = ktx.db_pool.get()?;
+
+        blogposts::comment::set_status(&conn, id, blogposts::comment::Status::Rejected)
+            .map_err(|err| comment_error("Failed to reject comment", err))
+    }
+
+    fn delete_comment(ktx: &Kontext, id: i32) -> juniper::FieldResult<blogposts::comment::Comment> {
+        ktx.authorize()?;
+        let conn = ktx.db_pool.get()?;
+
+        blogposts::comment::delete(&conn, id)
+            .map_err(|err| comment_error("Failed to delete comment", err))
+    }
+
+    #[graphql(description = "Remove an uploaded file")]
+    fn delete_media(ktx: &Kontext, id: i32) -> juniper::FieldResult<media::Media> {
+        ktx.authorize()?;
+        let conn = ktx.db_pool.get()?;
+
+        media::delete(&conn, ktx.media_dir.as_str(), id)
+            .map_err(|err| media_error("Failed to delete media", err))
+    }
 }
 
 pub type Schema = RootNode<'static, Query, Mutation>;
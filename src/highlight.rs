@@ -0,0 +1,452 @@
+use crate::feed::escape;
+
+/// The kinds of token code is split into. Each one is a class on the span
+/// around the token, and gets a colour in the theme.
+#[derive(Clone, Copy, PartialEq)]
+enum Class {
+    Comment,
+    String,
+    Number,
+    Keyword,
+    Type,
+    Operator,
+}
+
+struct Grammar {
+    keywords: &'static [&'static str],
+    line_comment: &'static str,
+    block_comment: (&'static str, &'static str),
+    /// Whether `'a` without a closing quote is a lifetime rather than a
+    /// broken character literal
+    lifetimes: bool,
+    /// Whether `r"..."` and `r#"..."#` are strings without escapes
+    raw_strings: bool,
+    /// Whether `"""` opens a string that runs over several lines
+    triple_quoted_strings: bool,
+}
+
+const RUST: Grammar = Grammar {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+        "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+        "true", "type", "unsafe", "use", "where", "while",
+    ],
+    line_comment: "//",
+    block_comment: ("/*", "*/"),
+    lifetimes: true,
+    raw_strings: true,
+    triple_quoted_strings: false,
+};
+
+const ELM: Grammar = Grammar {
+    keywords: &[
+        "alias", "as", "case", "else", "exposing", "if", "import", "in", "let", "module", "of",
+        "port", "then", "type",
+    ],
+    line_comment: "--",
+    block_comment: ("{-", "-}"),
+    lifetimes: false,
+    raw_strings: false,
+    triple_quoted_strings: true,
+};
+
+const OPERATOR_CHARS: &str = "+-*/=<>!&|^%:.?~\\@$";
+
+const THEME: [(Class, &str); 6] = [
+    (Class::Comment, "color: #8a8a7a; font-style: italic;"),
+    (Class::String, "color: #a6c44a;"),
+    (Class::Number, "color: #d58f4a;"),
+    (Class::Keyword, "color: #c678dd; font-weight: bold;"),
+    (Class::Type, "color: #4ab0d5;"),
+    (Class::Operator, "color: #d5c64a;"),
+];
+
+impl Class {
+    fn as_str(self) -> &'static str {
+        match self {
+            Class::Comment => "hl-comment",
+            Class::String => "hl-string",
+            Class::Number => "hl-number",
+            Class::Keyword => "hl-keyword",
+            Class::Type => "hl-type",
+            Class::Operator => "hl-operator",
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// API //
+////////////////////////////////////////////////////////////////////////////////
+
+/// Whether code fenced as `language` can be highlighted
+pub fn supports(language: &str) -> bool {
+    grammar(language).is_some()
+}
+
+/// The code as html, with every token wrapped in a span classed by what
+/// kind of token it is. None for languages there is no grammar for.
+pub fn highlight(language: &str, code: &str) -> Option<String> {
+    let grammar = grammar(language)?;
+
+    let mut buf = String::new();
+    let mut rest = code;
+
+    while let Some(first) = rest.chars().next() {
+        let (length, class) = token(grammar, rest, first);
+        let (text, remaining) = rest.split_at(length);
+
+        match class {
+            Some(class) => {
+                buf.push_str("<span class=\"");
+                buf.push_str(class.as_str());
+                buf.push_str("\">");
+                buf.push_str(escape(text).as_str());
+                buf.push_str("</span>");
+            }
+            None => buf.push_str(escape(text).as_str()),
+        }
+
+        rest = remaining;
+    }
+
+    Some(buf)
+}
+
+/// The stylesheet that colours highlighted code
+pub fn css() -> String {
+    let mut buf = String::new();
+
+    for (class, style) in THEME.iter() {
+        buf.push_str("code .");
+        buf.push_str(class.as_str());
+        buf.push_str(" { ");
+        buf.push_str(style);
+        buf.push_str(" }\n");
+    }
+
+    buf
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// LEXING //
+////////////////////////////////////////////////////////////////////////////////
+
+fn grammar(language: &str) -> Option<&'static Grammar> {
+    match language {
+        "rust" | "rs" => Some(&RUST),
+        "elm" => Some(&ELM),
+        _ => None,
+    }
+}
+
+/// How many bytes the token at the start of `rest` is, and what kind it is
+fn token(grammar: &Grammar, rest: &str, first: char) -> (usize, Option<Class>) {
+    if rest.starts_with(grammar.line_comment) {
+        return (rest.find('\n').unwrap_or(rest.len()), Some(Class::Comment));
+    }
+
+    if rest.starts_with(grammar.block_comment.0) {
+        return (block_comment_length(grammar, rest), Some(Class::Comment));
+    }
+
+    if grammar.triple_quoted_strings && rest.starts_with("\"\"\"") {
+        let length = rest[3..]
+            .find("\"\"\"")
+            .map(|index| index + 6)
+            .unwrap_or(rest.len());
+
+        return (length, Some(Class::String));
+    }
+
+    if grammar.raw_strings {
+        if let Some(length) = raw_string_length(rest) {
+            return (length, Some(Class::String));
+        }
+    }
+
+    if first == '"' {
+        return (quoted_length(rest, '"'), Some(Class::String));
+    }
+
+    if first == '\'' {
+        if let Some(length) = char_literal_length(rest) {
+            return (length, Some(Class::String));
+        }
+
+        if grammar.lifetimes {
+            return (1 + word_length(&rest[1..]), None);
+        }
+
+        return (quoted_length(rest, '\''), Some(Class::String));
+    }
+
+    if first.is_ascii_digit() {
+        return (number_length(rest), Some(Class::Number));
+    }
+
+    if first.is_alphabetic() || first == '_' {
+        let length = word_length(rest);
+        let word = &rest[..length];
+
+        let class = if grammar.keywords.contains(&word) {
+            Some(Class::Keyword)
+        } else if first.is_uppercase() {
+            Some(Class::Type)
+        } else {
+            None
+        };
+
+        return (length, class);
+    }
+
+    if OPERATOR_CHARS.contains(first) {
+        let length = rest
+            .find(|c: char| !OPERATOR_CHARS.contains(c))
+            .unwrap_or(rest.len());
+
+        return (length, Some(Class::Operator));
+    }
+
+    (first.len_utf8(), None)
+}
+
+/// Block comments nest in both Rust and Elm
+fn block_comment_length(grammar: &Grammar, rest: &str) -> usize {
+    let (open, close) = grammar.block_comment;
+    let mut depth = 0;
+    let mut index = 0;
+
+    while index < rest.len() {
+        if rest[index..].starts_with(open) {
+            depth += 1;
+            index += open.len();
+        } else if rest[index..].starts_with(close) {
+            depth -= 1;
+            index += close.len();
+
+            if depth == 0 {
+                return index;
+            }
+        } else {
+            index += rest[index..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+
+    rest.len()
+}
+
+/// The length of a string opened by `quote`, honouring backslash escapes
+fn quoted_length(rest: &str, quote: char) -> usize {
+    let mut escaped = false;
+
+    for (index, c) in rest.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return index + c.len_utf8();
+        }
+    }
+
+    rest.len()
+}
+
+/// `r"..."`, `r#"..."#` and their byte string forms
+fn raw_string_length(rest: &str) -> Option<usize> {
+    let after_prefix = rest.strip_prefix("br").or_else(|| rest.strip_prefix('r'))?;
+    let hashes = after_prefix.len() - after_prefix.trim_start_matches('#').len();
+
+    if !after_prefix[hashes..].starts_with('"') {
+        return None;
+    }
+
+    let mut closing = String::from("\"");
+    closing.push_str(&after_prefix[..hashes]);
+
+    let body_start = rest.len() - after_prefix.len() + hashes + 1;
+
+    Some(
+        rest[body_start..]
+            .find(closing.as_str())
+            .map(|index| body_start + index + closing.len())
+            .unwrap_or(rest.len()),
+    )
+}
+
+/// `'a'` or `'\n'`, but not the `'a` of a lifetime
+fn char_literal_length(rest: &str) -> Option<usize> {
+    let mut chars = rest.char_indices().skip(1);
+
+    match chars.next()? {
+        (_, '\\') => {
+            let escaped = rest[2..].chars().next()?.len_utf8();
+            let close = rest[2 + escaped..].find('\'')?;
+
+            if close <= 8 {
+                Some(2 + escaped + close + 1)
+            } else {
+                None
+            }
+        }
+        (_, '\'') => None,
+        _ => match chars.next()? {
+            (index, '\'') => Some(index + 1),
+            _ => None,
+        },
+    }
+}
+
+fn word_length(rest: &str) -> usize {
+    rest.find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(rest.len())
+}
+
+/// Digits, letters for hex and suffixes, and decimal points, but not the
+/// `..` of a range
+fn number_length(rest: &str) -> usize {
+    let mut length = 0;
+
+    for (index, c) in rest.char_indices() {
+        let is_decimal_point =
+            c == '.' && rest[index + 1..].starts_with(|next: char| next.is_ascii_digit());
+
+        if c.is_ascii_alphanumeric() || c == '_' || is_decimal_point {
+            length = index + 1;
+        } else {
+            break;
+        }
+    }
+
+    length
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// TESTS //
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The code split the way `highlight` splits it, leaving out the tokens
+    /// that get no class
+    fn classed<'a>(language: &str, code: &'a str) -> Vec<(&'a str, &'static str)> {
+        let grammar = grammar(language).unwrap();
+
+        let mut tokens = Vec::new();
+        let mut rest = code;
+
+        while let Some(first) = rest.chars().next() {
+            let (length, class) = token(grammar, rest, first);
+            let (text, remaining) = rest.split_at(length);
+
+            if let Some(class) = class {
+                tokens.push((text, class.as_str()));
+            }
+
+            rest = remaining;
+        }
+
+        tokens
+    }
+
+    #[test]
+    fn block_comments_nest() {
+        assert_eq!(
+            classed("rust", "/* a /* b */ c */ x"),
+            vec![("/* a /* b */ c */", "hl-comment")]
+        );
+        assert_eq!(
+            classed("elm", "{- a {- b -} c -} x"),
+            vec![("{- a {- b -} c -}", "hl-comment")]
+        );
+    }
+
+    #[test]
+    fn escaped_quotes_do_not_end_strings() {
+        assert_eq!(
+            classed("rust", r#""a \"b\" c" d"#),
+            vec![(r#""a \"b\" c""#, "hl-string")]
+        );
+        assert_eq!(
+            classed("rust", r#""a\\" b"#),
+            vec![(r#""a\\""#, "hl-string")]
+        );
+    }
+
+    #[test]
+    fn char_literals_are_strings_and_lifetimes_are_not() {
+        assert_eq!(
+            classed("rust", r"'a' '\n' '\'' '\u{1F600}'"),
+            vec![
+                ("'a'", "hl-string"),
+                (r"'\n'", "hl-string"),
+                (r"'\''", "hl-string"),
+                (r"'\u{1F600}'", "hl-string"),
+            ]
+        );
+        assert_eq!(
+            classed("rust", "fn f<'a>(x: &'a str)"),
+            vec![
+                ("fn", "hl-keyword"),
+                ("<", "hl-operator"),
+                (">", "hl-operator"),
+                (":", "hl-operator"),
+                ("&", "hl-operator"),
+            ]
+        );
+        assert_eq!(classed("elm", "'a'"), vec![("'a'", "hl-string")]);
+    }
+
+    #[test]
+    fn raw_strings_have_no_escapes() {
+        assert_eq!(
+            classed("rust", r###"r#"a "b" \"# c"###),
+            vec![(r###"r#"a "b" \"#"###, "hl-string")]
+        );
+        assert_eq!(
+            classed("rust", r#"br"\" x"#),
+            vec![(r#"br"\""#, "hl-string")]
+        );
+        assert_eq!(
+            classed("rust", "r + 1"),
+            vec![("+", "hl-operator"), ("1", "hl-number")]
+        );
+        assert_eq!(classed("elm", r#"r"a""#), vec![(r#""a""#, "hl-string")]);
+    }
+
+    #[test]
+    fn unterminated_tokens_run_to_the_end() {
+        for (language, code, class) in [
+            ("rust", "/* a /* b */", "hl-comment"),
+            ("rust", "\"a \\\" b", "hl-string"),
+            ("rust", "r##\"a \"# b", "hl-string"),
+            ("elm", "\"\"\"a\nb", "hl-string"),
+            ("elm", "{- a", "hl-comment"),
+            ("elm", "'a", "hl-string"),
+        ] {
+            assert_eq!(classed(language, code), vec![(code, class)]);
+        }
+
+        // Neither a char literal nor a lifetime, and still no panic
+        assert_eq!(classed("rust", "'\\"), vec![("\\", "hl-operator")]);
+        assert_eq!(classed("rust", "\"\\"), vec![("\"\\", "hl-string")]);
+    }
+
+    #[test]
+    fn html_in_code_is_escaped() {
+        assert_eq!(
+            highlight("rust", "a < b && \"<i>\"").unwrap(),
+            "a <span class=\"hl-operator\">&lt;</span> b \
+             <span class=\"hl-operator\">&amp;&amp;</span> \
+             <span class=\"hl-string\">&quot;&lt;i&gt;&quot;</span>"
+        );
+        assert_eq!(
+            highlight("elm", "-- <b> & c").unwrap(),
+            "<span class=\"hl-comment\">-- &lt;b&gt; &amp; c</span>"
+        );
+        assert_eq!(highlight("python", "a < b"), None);
+    }
+}
@@ -0,0 +1,546 @@
+use crate::kontext::Kontext;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{ColorType, DynamicImage, ImageFormat, ImageOutputFormat};
+use juniper::GraphQLObject;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{BufReader, Cursor};
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Where the images that can be resized live
+pub const SOURCE_DIR: &str = "./ui/public";
+
+/// The widths images can be asked for at. Keeping to a few of them keeps
+/// the cache from growing without bound.
+pub const WIDTHS: [u32; 5] = [320, 640, 960, 1280, 1920];
+
+/// Variants are made again when their source changes, so they can only be
+/// cached for a while
+pub const CACHE_CONTROL: &str = "public, max-age=86400";
+
+const JPEG_QUALITY: u8 = 85;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Png,
+    Jpeg,
+    WebP,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(
+    Context = Kontext,
+    description = "An image under ui/public, and the sizes it can be served at"
+)]
+pub struct Image {
+    pub path: String,
+    pub width: i32,
+    pub height: i32,
+    pub variants: Vec<Variant>,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(Context = Kontext, description = "One size and format an image can be served at")]
+pub struct Variant {
+    pub url: String,
+    pub width: i32,
+    pub height: i32,
+    pub content_type: String,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// FORMAT //
+////////////////////////////////////////////////////////////////////////////////
+
+impl Format {
+    fn from_image_format(image_format: ImageFormat) -> Option<Format> {
+        match image_format {
+            ImageFormat::Png => Some(Format::Png),
+            ImageFormat::Jpeg => Some(Format::Jpeg),
+            ImageFormat::WebP => Some(Format::WebP),
+            _ => None,
+        }
+    }
+
+    /// Whether a WebP variant can be offered for images in this format.
+    /// Only lossless WebP can be encoded here, which comes out smaller than
+    /// PNG but bigger than JPEG.
+    fn offers_webp(self) -> bool {
+        match self {
+            Format::Png | Format::WebP => true,
+            Format::Jpeg => false,
+        }
+    }
+
+    pub fn from_extension(extension: &str) -> Option<Format> {
+        match extension.to_lowercase().as_str() {
+            "png" => Some(Format::Png),
+            "jpg" | "jpeg" => Some(Format::Jpeg),
+            "webp" => Some(Format::WebP),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Format> {
+        Format::from_extension(path.extension()?.to_string_lossy().as_ref())
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Png => "image/png",
+            Format::Jpeg => "image/jpeg",
+            Format::WebP => "image/webp",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Jpeg => "jpg",
+            Format::WebP => "webp",
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// API //
+////////////////////////////////////////////////////////////////////////////////
+
+/// The image at `path` under the source directory, if there is one. Paths
+/// that try to leave the directory are never found.
+pub fn source_path(path: &str) -> Option<PathBuf> {
+    let relative = Path::new(path);
+
+    if relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+
+    let full_path = Path::new(SOURCE_DIR).join(relative);
+
+    Format::from_path(&full_path)?;
+
+    if full_path.is_file() {
+        Some(full_path)
+    } else {
+        None
+    }
+}
+
+/// The image at `source`, no wider than `width`, encoded as `format` or as
+/// the source is. WebP is only given when it comes out smaller than the
+/// source's own format, so the format given back can differ from the one
+/// asked for. Variants are made the first time they are asked for and kept
+/// in `cache_dir` after that, and the ones made from older versions of the
+/// source are removed. Metadata like EXIF is never copied over.
+pub fn variant(
+    cache_dir: &str,
+    source: &Path,
+    width: Option<u32>,
+    format: Option<Format>,
+) -> Result<(Vec<u8>, Format), String> {
+    let source_format = match Format::from_path(source) {
+        Some(format) => format,
+        None => return Err("Unsupported image format".to_string()),
+    };
+
+    let format = format.unwrap_or(source_format);
+
+    let version = source_version(source)?;
+
+    let cache_path =
+        Path::new(cache_dir).join(cache_filename(source, version.as_str(), width, format));
+
+    if let Ok(bytes) = fs::read(&cache_path) {
+        // The cached bytes can be in the source's format, when WebP was not
+        // smaller
+        let cached_format = image::guess_format(&bytes)
+            .ok()
+            .and_then(Format::from_image_format)
+            .unwrap_or(format);
+
+        return Ok((bytes, cached_format));
+    }
+
+    let image = load(source)?;
+
+    let image = match width {
+        Some(width) if width < image.width() => image.resize(width, u32::MAX, FilterType::Lanczos3),
+        _ => image,
+    };
+
+    let (bytes, format) = if format == Format::WebP && source_format != Format::WebP {
+        let webp_bytes = encode(&image, Format::WebP)?;
+        let source_bytes = encode(&image, source_format)?;
+
+        if webp_bytes.len() < source_bytes.len() {
+            (webp_bytes, Format::WebP)
+        } else {
+            (source_bytes, source_format)
+        }
+    } else {
+        (encode(&image, format)?, format)
+    };
+
+    // Written somewhere else first, so that a request for the same variant
+    // never reads half a file
+    let mut temp_path = cache_path.clone();
+    temp_path.set_extension(format!("{}.tmp", rand::random::<u32>()));
+
+    fs::create_dir_all(cache_dir).map_err(|err| err.to_string())?;
+    fs::write(&temp_path, &bytes).map_err(|err| err.to_string())?;
+    fs::rename(&temp_path, &cache_path).map_err(|err| err.to_string())?;
+
+    remove_stale_variants(cache_dir, source, version.as_str());
+
+    Ok((bytes, format))
+}
+
+/// The dimensions of the image at `path` under the source directory, and
+/// of every variant of it
+pub fn describe(path: &str) -> Result<Option<Image>, String> {
+    let source = match source_path(path) {
+        Some(source) => source,
+        None => return Ok(None),
+    };
+
+    let source_format = match Format::from_path(&source) {
+        Some(format) => format,
+        None => return Ok(None),
+    };
+
+    let (width, height) = {
+        let (width, height) = image::image_dimensions(&source).map_err(|err| err.to_string())?;
+
+        // Images turned on their side by their orientation swap dimensions
+        if orientation(&source) >= 5 {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    };
+
+    let mut widths: Vec<u32> = WIDTHS.iter().copied().filter(|w| *w < width).collect();
+    widths.push(width);
+
+    let mut variants: Vec<Variant> = Vec::new();
+
+    for variant_width in widths {
+        let variant_height = ((height as f64) * (variant_width as f64) / (width as f64))
+            .round()
+            .max(1.0) as u32;
+
+        let formats = if source_format == Format::WebP || !source_format.offers_webp() {
+            vec![source_format]
+        } else {
+            vec![source_format, Format::WebP]
+        };
+
+        for format in formats {
+            variants.push(Variant {
+                url: url(
+                    path,
+                    Some(variant_width).filter(|w| *w < width),
+                    Some(format).filter(|f| *f != source_format),
+                ),
+                width: variant_width as i32,
+                height: variant_height as i32,
+                content_type: format.content_type().to_string(),
+            });
+        }
+    }
+
+    Ok(Some(Image {
+        path: path.to_string(),
+        width: width as i32,
+        height: height as i32,
+        variants,
+    }))
+}
+
+pub fn url(path: &str, width: Option<u32>, format: Option<Format>) -> String {
+    let mut buf = String::new();
+
+    buf.push_str("/images/");
+    buf.push_str(path);
+
+    let mut separator = '?';
+
+    if let Some(width) = width {
+        buf.push(separator);
+        buf.push_str("w=");
+        buf.push_str(width.to_string().as_str());
+
+        separator = '&';
+    }
+
+    if let Some(format) = format {
+        buf.push(separator);
+        buf.push_str("format=");
+        buf.push_str(format.extension());
+    }
+
+    buf
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// HELPERS //
+////////////////////////////////////////////////////////////////////////////////
+
+/// Which version of the source variants are made from, going by when it was
+/// last changed and how big it is
+fn source_version(source: &Path) -> Result<String, String> {
+    let metadata = fs::metadata(source).map_err(|err| err.to_string())?;
+
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_nanos())
+        .unwrap_or(0);
+
+    let mut buf = String::new();
+
+    buf.push_str(modified.to_string().as_str());
+    buf.push('_');
+    buf.push_str(metadata.len().to_string().as_str());
+
+    Ok(buf)
+}
+
+/// What every cache file made from `source` starts with
+fn cache_prefix(source: &Path) -> String {
+    let mut hasher = Sha256::new();
+
+    hasher.update(source.to_string_lossy().as_bytes());
+
+    let mut buf = format!("{:x}", hasher.finalize());
+
+    buf.push('-');
+
+    buf
+}
+
+/// Named after everything the variant is made from, including the version
+/// of the source, so that changed sources get new variants
+fn cache_filename(source: &Path, version: &str, width: Option<u32>, format: Format) -> String {
+    let mut buf = cache_prefix(source);
+
+    buf.push_str(version);
+    buf.push('-');
+    buf.push_str(width.unwrap_or(0).to_string().as_str());
+    buf.push('.');
+    buf.push_str(format.extension());
+
+    buf
+}
+
+/// Remove the variants made from versions of `source` older than `version`.
+/// Failing to only leaves them taking up space, so errors are ignored.
+fn remove_stale_variants(cache_dir: &str, source: &Path, version: &str) {
+    let prefix = cache_prefix(source);
+
+    let mut current = prefix.clone();
+    current.push_str(version);
+    current.push('-');
+
+    let entries = match fs::read_dir(cache_dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let filename = entry.file_name().to_string_lossy().to_string();
+
+        if filename.starts_with(prefix.as_str()) && !filename.starts_with(current.as_str()) {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Decode the image, turned the way its EXIF orientation says it should be
+fn load(source: &Path) -> Result<DynamicImage, String> {
+    let image = image::io::Reader::open(source)
+        .map_err(|err| err.to_string())?
+        .with_guessed_format()
+        .map_err(|err| err.to_string())?
+        .decode()
+        .map_err(|err| err.to_string())?;
+
+    Ok(match orientation(source) {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    })
+}
+
+/// The EXIF orientation of the image, 1 meaning it is already upright
+fn orientation(source: &Path) -> u32 {
+    fs::File::open(source)
+        .ok()
+        .and_then(|file| {
+            exif::Reader::new()
+                .read_from_container(&mut BufReader::new(file))
+                .ok()
+        })
+        .and_then(|exif| {
+            exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+                .and_then(|field| field.value.get_uint(0))
+        })
+        .unwrap_or(1)
+}
+
+fn encode(image: &DynamicImage, format: Format) -> Result<Vec<u8>, String> {
+    let mut bytes: Vec<u8> = Vec::new();
+
+    match format {
+        Format::Png => image
+            .write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)
+            .map_err(|err| err.to_string())?,
+        Format::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_to(
+                &mut Cursor::new(&mut bytes),
+                ImageOutputFormat::Jpeg(JPEG_QUALITY),
+            )
+            .map_err(|err| err.to_string())?,
+        Format::WebP => {
+            let rgba = image.to_rgba8();
+
+            // An alpha channel nothing shows through only makes the file
+            // bigger
+            if rgba.pixels().all(|pixel| pixel[3] == u8::MAX) {
+                let rgb = image.to_rgb8();
+
+                WebPEncoder::new_lossless(&mut bytes)
+                    .encode(rgb.as_raw(), rgb.width(), rgb.height(), ColorType::Rgb8)
+                    .map_err(|err| err.to_string())?
+            } else {
+                WebPEncoder::new_lossless(&mut bytes)
+                    .encode(rgba.as_raw(), rgba.width(), rgba.height(), ColorType::Rgba8)
+                    .map_err(|err| err.to_string())?
+            }
+        }
+    }
+
+    Ok(bytes)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// TESTS //
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("images-test-{}", rand::random::<u32>()));
+
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    /// Colors that change from pixel to pixel, which compress badly without
+    /// losing anything
+    fn noise(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |_, _| {
+            Rgb([rand::random(), rand::random(), rand::random()])
+        })
+    }
+
+    fn write(path: &Path, image: DynamicImage, format: Format) {
+        fs::write(path, encode(&image, format).unwrap()).unwrap();
+    }
+
+    /// Whether a lossless WebP says it has an alpha channel. The bit sits
+    /// after the image's width and height, in the header of the VP8L chunk.
+    fn has_alpha(webp: &[u8]) -> bool {
+        assert_eq!(&webp[12..16], b"VP8L");
+
+        let header = u32::from_le_bytes([webp[21], webp[22], webp[23], webp[24]]);
+
+        header >> 28 & 1 == 1
+    }
+
+    #[test]
+    fn opaque_images_are_encoded_without_alpha() {
+        let opaque = RgbaImage::from_pixel(16, 16, Rgba([10, 20, 30, u8::MAX]));
+        let mut transparent = opaque.clone();
+        transparent.put_pixel(0, 0, Rgba([10, 20, 30, 0]));
+
+        let opaque_webp = encode(&DynamicImage::ImageRgba8(opaque), Format::WebP).unwrap();
+        let transparent_webp =
+            encode(&DynamicImage::ImageRgba8(transparent), Format::WebP).unwrap();
+
+        assert!(!has_alpha(&opaque_webp));
+        assert!(has_alpha(&transparent_webp));
+    }
+
+    #[test]
+    fn webp_is_only_given_when_it_is_smaller() {
+        let dir = temp_dir();
+        let cache_dir = dir.join("cache");
+        let source = dir.join("photo.jpg");
+
+        write(
+            &source,
+            DynamicImage::ImageRgb8(noise(64, 64)),
+            Format::Jpeg,
+        );
+
+        for _ in 0..2 {
+            let (bytes, format) = variant(
+                cache_dir.to_str().unwrap(),
+                &source,
+                None,
+                Some(Format::WebP),
+            )
+            .unwrap();
+
+            assert!(format == Format::Jpeg);
+            assert_eq!(image::guess_format(&bytes).unwrap(), ImageFormat::Jpeg);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn variants_of_changed_sources_replace_the_old_ones() {
+        let dir = temp_dir();
+        let cache_dir = dir.join("cache");
+        let source = dir.join("drawing.png");
+
+        let cached_files = || fs::read_dir(&cache_dir).unwrap().count();
+
+        write(&source, DynamicImage::ImageRgb8(noise(32, 32)), Format::Png);
+        variant(cache_dir.to_str().unwrap(), &source, None, None).unwrap();
+        variant(
+            cache_dir.to_str().unwrap(),
+            &source,
+            None,
+            Some(Format::WebP),
+        )
+        .unwrap();
+
+        assert_eq!(cached_files(), 2);
+
+        write(&source, DynamicImage::ImageRgb8(noise(48, 48)), Format::Png);
+        let (bytes, _) = variant(cache_dir.to_str().unwrap(), &source, None, None).unwrap();
+
+        assert_eq!(cached_files(), 1);
+        assert_eq!(image::load_from_memory(&bytes).unwrap().width(), 48);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
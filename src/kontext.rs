@@ -0,0 +1,54 @@
+use crate::blogposts;
+use crate::db::Pool;
+use crate::search;
+use crate::webmention;
+use juniper::{FieldError, FieldResult};
+
+/// What every GraphQL resolver gets, wherever it is defined
+pub struct Kontext {
+    pub db_pool: Pool,
+    pub password: String,
+    pub credential: Option<String>,
+    pub comment_secret: String,
+    pub search_index: search::SharedIndex,
+    pub media_dir: String,
+    /// The address the request came from, for rate limiting
+    pub client_address: Option<String>,
+    pub site_url: String,
+    pub webmentions: webmention::Queue,
+}
+
+impl Kontext {
+    pub fn authorize(&self) -> FieldResult<()> {
+        match &self.credential {
+            Some(credential) if credential == &self.password => Ok(()),
+            _ => Err(FieldError::new(
+                "Unauthorized",
+                graphql_value!({ "code": "UNAUTHORIZED" }),
+            )),
+        }
+    }
+
+    /// Let the pages a post links to know about it, once readers can see
+    /// it. Scheduled posts are picked up by the worker when they go up.
+    pub fn send_webmentions(&self, post: &blogposts::v2::Post) {
+        if post.is_visible(blogposts::v2::now()) {
+            self.webmentions.push(webmention::Job::NotifyUnsent);
+        }
+    }
+}
+
+impl juniper::Context for Kontext {}
+
+pub fn bad_input(msg: &str) -> FieldError {
+    FieldError::new(msg, graphql_value!({ "code": "BAD_INPUT" }))
+}
+
+/// How many items a field with a `limit` argument gives back
+pub fn check_limit(limit: Option<i32>, default: usize) -> FieldResult<usize> {
+    match limit {
+        None => Ok(default),
+        Some(limit) if limit < 0 => Err(bad_input("limit must not be negative")),
+        Some(limit) => Ok(limit as usize),
+    }
+}
@@ -0,0 +1,373 @@
+use crate::blogposts::v2::{self, Post};
+use crate::feed;
+use crate::images;
+use crate::kontext::Kontext;
+use crate::markdown;
+use crate::media;
+use crate::routes;
+use diesel::mysql::MysqlConnection;
+use diesel::QueryResult;
+use juniper::GraphQLObject;
+use std::collections::HashMap;
+use std::time::Duration;
+use url::Url;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(GraphQLObject)]
+#[graphql(
+    Context = Kontext,
+    Scalar = juniper::DefaultScalarValue,
+    description = "A blog post, and the links in it that go nowhere"
+)]
+pub struct PostLinkReport {
+    pub post: Post,
+    pub broken: Vec<BrokenLink>,
+}
+
+#[derive(GraphQLObject)]
+#[graphql(
+    Context = Kontext,
+    description = "A link or image in a post that goes nowhere, or to a post's old address"
+)]
+pub struct BrokenLink {
+    #[graphql(description = "The address exactly as it is written in the post")]
+    pub url: String,
+    pub is_image: bool,
+    #[graphql(description = "What went wrong following it")]
+    pub reason: String,
+}
+
+/// Where a link in a post goes
+enum Destination {
+    /// A path on this site, and its query if it has one
+    Internal(String, Option<String>),
+    External(Url),
+    /// Links within the post, to email addresses and the like
+    Unchecked,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// API //
+////////////////////////////////////////////////////////////////////////////////
+
+/// Every post with broken links or images in it, and what is wrong with
+/// them. Links to other sites are only followed when `check_external` is
+/// set, so that checking works without a network.
+pub fn check(
+    conn: &MysqlConnection,
+    site_url: &str,
+    check_external: bool,
+) -> QueryResult<Vec<PostLinkReport>> {
+    let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+
+    // Posts often link to the same pages, which only need asking once
+    let mut external_results: HashMap<String, Option<String>> = HashMap::new();
+
+    let mut reports: Vec<PostLinkReport> = Vec::new();
+
+    for post in v2::list(conn)? {
+        let mut broken: Vec<BrokenLink> = Vec::new();
+
+        for (url, is_image) in markdown::links_and_images(post.content.as_str()) {
+            let reason = match destination(site_url, &post, url.as_str()) {
+                Destination::Internal(path, query) => {
+                    check_internal(conn, path.as_str(), query.as_deref())?
+                }
+                Destination::External(external_url) if check_external => external_results
+                    .entry(external_url.to_string())
+                    .or_insert_with(|| check_external_url(&agent, &external_url))
+                    .clone(),
+                Destination::External(_) | Destination::Unchecked => None,
+            };
+
+            if let Some(reason) = reason {
+                broken.push(BrokenLink {
+                    url,
+                    is_image,
+                    reason,
+                });
+            }
+        }
+
+        if !broken.is_empty() {
+            reports.push(PostLinkReport { post, broken });
+        }
+    }
+
+    Ok(reports)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// HELPERS //
+////////////////////////////////////////////////////////////////////////////////
+
+/// Links are resolved the way a reader's browser would, from the page the
+/// post is served on
+fn destination(site_url: &str, post: &Post, url: &str) -> Destination {
+    if url.is_empty() || url.starts_with('#') {
+        return Destination::Unchecked;
+    }
+
+    let base = match Url::parse(feed::url(site_url, post.path().as_str()).as_str()) {
+        Ok(base) => base,
+        Err(_) => return Destination::Unchecked,
+    };
+
+    let resolved = match base.join(url) {
+        Ok(resolved) => resolved,
+        Err(_) => return Destination::Unchecked,
+    };
+
+    if resolved.scheme() != "http" && resolved.scheme() != "https" {
+        return Destination::Unchecked;
+    }
+
+    if resolved.origin() == base.origin() {
+        Destination::Internal(
+            resolved.path().to_string(),
+            resolved.query().map(|query| query.to_string()),
+        )
+    } else {
+        Destination::External(resolved)
+    }
+}
+
+/// Why nothing is served at `path`, or None if something is
+fn check_internal(
+    conn: &MysqlConnection,
+    path: &str,
+    query: Option<&str>,
+) -> QueryResult<Option<String>> {
+    let path = if path.len() > 1 {
+        path.trim_end_matches('/')
+    } else {
+        path
+    };
+
+    if is_route(path) {
+        return Ok(None);
+    }
+
+    if let Some(post_slug) = path.strip_prefix("/blog/") {
+        return match v2::find_visible_by_slug(conn, post_slug) {
+            Ok(post) if post.slug == post_slug => Ok(None),
+            // The old slug of a renamed post, which readers get redirected
+            // from, but which is better updated
+            Ok(post) => {
+                let mut buf = String::new();
+
+                buf.push_str("Moved to ");
+                buf.push_str(post.path().as_str());
+
+                Ok(Some(buf))
+            }
+            Err(diesel::result::Error::NotFound) => {
+                Ok(Some("No published post lives here".to_string()))
+            }
+            Err(err) => Err(err),
+        };
+    }
+
+    if let Some(filename) = path.strip_prefix("/media/") {
+        let is_stored = media::is_valid_filename(filename)
+            && media::find_by_filename(conn, filename)?.is_some();
+
+        return Ok(if is_stored {
+            None
+        } else {
+            Some("No uploaded file has this name".to_string())
+        });
+    }
+
+    if let Some(image_path) = path.strip_prefix("/images/") {
+        return Ok(check_image(image_path, query));
+    }
+
+    Ok(Some("The site has no page here".to_string()))
+}
+
+/// Whether `path` is served by a route of its own, or a page of the ui
+fn is_route(path: &str) -> bool {
+    routes::PAGES.contains(&path)
+        || routes::UI_PREFIXES.iter().any(|prefix| {
+            path == *prefix
+                || path
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+}
+
+/// Images can only be asked for at the widths and in the formats the image
+/// route accepts
+fn check_image(image_path: &str, query: Option<&str>) -> Option<String> {
+    if images::source_path(image_path).is_none() {
+        return Some("No image lives here".to_string());
+    }
+
+    for pair in query.unwrap_or_default().split('&') {
+        match pair.split_once('=') {
+            Some(("w", width)) => {
+                let is_allowed = width
+                    .parse::<u32>()
+                    .is_ok_and(|width| images::WIDTHS.contains(&width));
+
+                if !is_allowed {
+                    return Some("The image is not served at this width".to_string());
+                }
+            }
+            Some(("format", format)) if images::Format::from_extension(format).is_none() => {
+                return Some("The image is not served in this format".to_string());
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Why the page at `url` could not be loaded, or None if it could. Some
+/// servers do not answer HEAD requests, so those get a GET instead.
+fn check_external_url(agent: &ureq::Agent, url: &Url) -> Option<String> {
+    let result = match agent.head(url.as_str()).call() {
+        Err(ureq::Error::Status(405, _)) | Err(ureq::Error::Status(501, _)) => {
+            agent.get(url.as_str()).call()
+        }
+        result => result,
+    };
+
+    match result {
+        Ok(_) => None,
+        Err(ureq::Error::Status(code, _)) => {
+            let mut buf = String::new();
+
+            buf.push_str("Responded with ");
+            buf.push_str(code.to_string().as_str());
+
+            Some(buf)
+        }
+        Err(err) => Some(err.to_string()),
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// TESTS //
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blogposts::v2::Status;
+
+    const SITE_URL: &str = "https://chadtech.us";
+
+    fn post(slug: &str) -> Post {
+        Post {
+            id: 1,
+            title: "Title".to_string(),
+            date: 0.0,
+            content: String::new(),
+            status: Status::Published,
+            publish_at: None,
+            slug: slug.to_string(),
+            content_html: None,
+            updated_at: 0.0,
+            uid: "uid".to_string(),
+            word_count: None,
+            reading_minutes: None,
+        }
+    }
+
+    fn internal(url: &str) -> Option<(String, Option<String>)> {
+        match destination(SITE_URL, &post("this-post"), url) {
+            Destination::Internal(path, query) => Some((path, query)),
+            _ => None,
+        }
+    }
+
+    fn is_unchecked(url: &str) -> bool {
+        matches!(
+            destination(SITE_URL, &post("this-post"), url),
+            Destination::Unchecked
+        )
+    }
+
+    #[test]
+    fn links_and_images_are_extracted_once_each() {
+        let found = markdown::links_and_images(
+            "[a](/blog/a) and [again](/blog/a)\n\n![pic](/images/pic.png) [pic](/images/pic.png)",
+        );
+
+        assert_eq!(
+            found,
+            vec![
+                ("/blog/a".to_string(), false),
+                ("/images/pic.png".to_string(), true),
+                ("/images/pic.png".to_string(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn links_in_code_are_not_extracted() {
+        let found = markdown::links_and_images("`[a](/nowhere)`\n\n    [b](/nowhere)");
+
+        assert!(found.is_empty());
+    }
+
+    #[test]
+    fn relative_links_resolve_from_the_post() {
+        assert_eq!(
+            internal("other-post"),
+            Some(("/blog/other-post".to_string(), None))
+        );
+        assert_eq!(
+            internal("../feed.xml"),
+            Some(("/feed.xml".to_string(), None))
+        );
+    }
+
+    #[test]
+    fn links_to_this_site_keep_their_query() {
+        assert_eq!(
+            internal("https://chadtech.us/images/pic.png?w=640"),
+            Some(("/images/pic.png".to_string(), Some("w=640".to_string())))
+        );
+    }
+
+    #[test]
+    fn links_to_other_sites_are_external() {
+        match destination(SITE_URL, &post("this-post"), "//example.com/page") {
+            Destination::External(url) => assert_eq!(url.as_str(), "https://example.com/page"),
+            _ => panic!("Expected an external link"),
+        }
+    }
+
+    #[test]
+    fn anchors_and_other_schemes_are_unchecked() {
+        assert!(is_unchecked(""));
+        assert!(is_unchecked("#heading"));
+        assert!(is_unchecked("mailto:someone@example.com"));
+    }
+
+    #[test]
+    fn routes_and_ui_pages_exist() {
+        assert!(is_route("/"));
+        assert!(is_route("/feed.xml"));
+        assert!(is_route("/admin"));
+        assert!(is_route("/admin/blog"));
+    }
+
+    #[test]
+    fn paths_that_only_start_like_routes_do_not_exist() {
+        assert!(!is_route("/administrator"));
+        assert!(!is_route("/feed"));
+        assert!(!is_route("/graphql"));
+    }
+
+    #[test]
+    fn images_outside_the_image_directory_do_not_exist() {
+        assert!(check_image("../Cargo.toml", None).is_some());
+        assert!(check_image("missing.png", None).is_some());
+    }
+}
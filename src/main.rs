@@ -7,15 +7,22 @@ extern crate r2d2_mysql;
 extern crate serde_json;
 
 use crate::db::Pool;
-use crate::flags::Flags;
+use crate::flags::{Flags, Mode};
 use crate::graphql_schema::{create_schema, Schema};
 use actix_cors::Cors;
+use actix_multipart::Multipart;
+use actix_web::error::BlockingError;
+use actix_web::http::header;
 use actix_web::middleware::Logger;
-use actix_web::{web, App, HttpResponse, HttpServer};
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use futures_util::StreamExt;
 use juniper::http::graphiql::graphiql_source;
 use juniper::http::GraphQLRequest;
 use notify::{raw_watcher, RecursiveMode, Watcher};
+use serde_derive::Deserialize;
+use std::collections::hash_map::DefaultHasher;
 use std::fs;
+use std::hash::{Hash, Hasher};
 use std::process::Command;
 use std::sync::mpsc::channel;
 use std::thread;
@@ -23,9 +30,22 @@ use std::thread;
 mod analytics;
 mod blogposts;
 mod db;
+mod export;
+mod feed;
 mod flags;
 mod graphql_schema;
+mod highlight;
+mod images;
+mod kontext;
+mod links;
+mod markdown;
+mod media;
+mod page;
+mod routes;
 mod schema;
+mod search;
+mod sitemap;
+mod webmention;
 
 ////////////////////////////////////////////////////////////////////////////////
 // TYPES //
@@ -35,7 +55,17 @@ mod schema;
 struct Modelka {
     pub ip_address: String,
     pub admin_password: String,
+    pub comment_secret: String,
+    pub client_address_header: Option<String>,
     pub port_number: u64,
+    pub site_url: String,
+    pub robots_disallow: Vec<String>,
+    pub mode: Mode,
+    pub out_dir: String,
+    pub posts_dir: String,
+    pub media_dir: String,
+    pub image_cache_dir: String,
+    pub check_external_links: bool,
     pub okoli: Okoli,
 }
 
@@ -57,7 +87,17 @@ impl Modelka {
         Ok(Modelka {
             ip_address: flags.ip_address,
             admin_password: flags.admin_password,
+            comment_secret: flags.comment_secret,
+            client_address_header: flags.client_address_header,
             port_number: flags.port_number,
+            site_url: flags.site_url,
+            robots_disallow: flags.robots_disallow,
+            mode: flags.mode,
+            out_dir: flags.out_dir,
+            posts_dir: flags.posts_dir,
+            media_dir: flags.media_dir,
+            image_cache_dir: flags.image_cache_dir,
+            check_external_links: flags.check_external_links,
             okoli,
         })
     }
@@ -99,10 +139,30 @@ async fn main() -> Result<(), String> {
 
     let modelka = Modelka::poca()?;
 
+    match modelka.mode {
+        Mode::ImportPosts => return import_posts(&pool, &modelka),
+        Mode::ExportPosts => return export_posts(&pool, &modelka),
+        Mode::CheckLinks => return check_links(&pool, &modelka),
+        Mode::Serve | Mode::ExportSite => {}
+    }
+
     let dev_mode = modelka.okoli.is_dev();
 
     write_frontend_api_code(&modelka).map_err(|err| err.to_string())?;
     compile_elm(&modelka.okoli)?;
+
+    // Posts saved before their html, word counts and related posts were stored
+    {
+        let conn = pool.get().map_err(|err| err.to_string())?;
+
+        blogposts::v2::cache_missing_html(&conn).map_err(|err| err.to_string())?;
+        blogposts::related::backfill(&conn).map_err(|err| err.to_string())?;
+    }
+
+    if modelka.mode == Mode::ExportSite {
+        return export_site(&pool, &modelka);
+    }
+
     compile_js(dev_mode)?;
 
     if dev_mode {
@@ -133,10 +193,16 @@ async fn main() -> Result<(), String> {
     let schema = create_schema();
     let web_schema = actix_web::web::Data::new(schema);
 
+    // Built on the first search, and again after any post changes
+    let search_index = search::SharedIndex::default();
+
     // Logging
-    std::env::set_var("RUST_LOG", "actix_web=info");
+    std::env::set_var("RUST_LOG", "actix_web=info,chadtechus=info");
     env_logger::init();
 
+    // Sends and verifies webmentions in the background
+    let webmentions = webmention::start(pool.clone(), modelka.site_url.clone());
+
     HttpServer::new(move || {
         let cors = Cors::permissive();
 
@@ -144,12 +210,25 @@ async fn main() -> Result<(), String> {
             .wrap(cors)
             .wrap(Logger::default())
             .data(pool.clone())
+            .data(search_index.clone())
+            .data(webmentions.clone())
             .app_data(web_schema.clone())
             .app_data(web_modelka.clone())
-            .route("/elm.js", web::get().to(elm_asset_route))
-            .route("/app.js", web::get().to(js_asset_route))
-            .route("/graphql", web::post().to(graphql))
-            .route("/graphiql", web::get().to(graphiql))
+            .route(routes::ELM_JS, web::get().to(elm_asset_route))
+            .route(routes::APP_JS, web::get().to(js_asset_route))
+            .route(routes::GRAPHQL, web::post().to(graphql))
+            .route(routes::GRAPHIQL, web::get().to(graphiql))
+            .route(routes::BLOG, web::get().to(blog_route))
+            .route("/blog/{slug}", web::get().to(blogpost_route))
+            .route(routes::RSS, web::get().to(rss_route))
+            .route(routes::ATOM, web::get().to(atom_route))
+            .route(routes::SITEMAP, web::get().to(sitemap_route))
+            .route(routes::ROBOTS, web::get().to(robots_route))
+            .route(routes::HIGHLIGHT_CSS, web::get().to(highlight_css_route))
+            .route(routes::MEDIA, web::post().to(upload_media_route))
+            .route("/media/{filename}", web::get().to(media_route))
+            .route("/images/{path:.*}", web::get().to(image_route))
+            .route(routes::WEBMENTION, web::post().to(webmention_route))
             .default_service(web::get().to(frontend))
     })
     .bind(socket_address)
@@ -163,6 +242,23 @@ async fn main() -> Result<(), String> {
 // HELPER //
 ////////////////////////////////////////////////////////////////////////////////
 
+/// The address a request came from. Behind a reverse proxy every request
+/// comes from the proxy, so the address is read from the header the proxy
+/// puts it in. Proxies add to the end of headers like X-Forwarded-For, so
+/// the last address is the one the proxy saw, and earlier ones could be
+/// anything the client sent.
+fn client_address(http_req: &HttpRequest, modelka: &Modelka) -> Option<String> {
+    match &modelka.client_address_header {
+        Some(header) => http_req
+            .headers()
+            .get(header.as_str())
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .map(|address| address.trim().to_string()),
+        None => http_req.peer_addr().map(|address| address.ip().to_string()),
+    }
+}
+
 fn ui_src(filename: &str) -> String {
     let mut buf = ui_dir("src/");
     buf.push_str(filename);
@@ -196,6 +292,74 @@ fn read_js_file() -> std::io::Result<String> {
     fs::read_to_string(ui_public("app.js"))
 }
 
+fn html_response(modelka: &Modelka, page: page::Page) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(page.render(modelka.site_url.as_str()))
+}
+
+fn http_date(millis: f64) -> String {
+    blogposts::v2::date_time(millis)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+/// Respond with `body`, unless the request shows the client already has this
+/// version of it, in which case respond with 304 Not Modified
+fn conditional_response(
+    req: &HttpRequest,
+    content_type: &str,
+    body: String,
+    last_modified: f64,
+) -> HttpResponse {
+    let etag = {
+        let mut hasher = DefaultHasher::new();
+        body.hash(&mut hasher);
+
+        let mut buf = String::new();
+
+        buf.push('"');
+        buf.push_str(format!("{:x}", hasher.finish()).as_str());
+        buf.push('"');
+
+        buf
+    };
+
+    let header_str = |name: header::HeaderName| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+    };
+
+    let not_modified = match header_str(header::IF_NONE_MATCH) {
+        Some(if_none_match) => if_none_match
+            .split(',')
+            .any(|tag| tag.trim() == etag || tag.trim() == "*"),
+        None => match header_str(header::IF_MODIFIED_SINCE)
+            .and_then(|since| chrono::DateTime::parse_from_rfc2822(since.as_str()).ok())
+        {
+            Some(since) => (last_modified / 1000.0) as i64 <= since.timestamp(),
+            None => false,
+        },
+    };
+
+    let mut res = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+
+    res.header(header::ETAG, etag)
+        .header(header::LAST_MODIFIED, http_date(last_modified));
+
+    if not_modified {
+        res.finish()
+    } else {
+        res.content_type(content_type).body(body)
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // ROUTES //
 ////////////////////////////////////////////////////////////////////////////////
@@ -211,11 +375,27 @@ async fn graphql(
     pool: web::Data<Pool>,
     schema: web::Data<Schema>,
     modelka: web::Data<Modelka>,
+    search_index: web::Data<search::SharedIndex>,
+    webmentions: web::Data<webmention::Queue>,
+    http_req: HttpRequest,
     req: web::Json<GraphQLRequest>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let ktx = graphql_schema::Kontext {
+    let credential = http_req
+        .headers()
+        .get(graphql_schema::ADMIN_PASSWORD_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string());
+
+    let ktx = kontext::Kontext {
         db_pool: pool.get_ref().to_owned(),
         password: modelka.get_ref().to_owned().admin_password,
+        comment_secret: modelka.comment_secret.clone(),
+        credential,
+        search_index: search_index.get_ref().clone(),
+        media_dir: modelka.media_dir.clone(),
+        client_address: client_address(&http_req, &modelka),
+        site_url: modelka.site_url.clone(),
+        webmentions: webmentions.get_ref().clone(),
     };
 
     let user = web::block(move || {
@@ -259,8 +439,421 @@ async fn js_asset_route(model: web::Data<Modelka>) -> HttpResponse {
     }
 }
 
-async fn frontend() -> HttpResponse {
-    HttpResponse::Ok().body(include_str!("./assets/index.html"))
+async fn blogpost_route(
+    http_req: HttpRequest,
+    pool: web::Data<Pool>,
+    modelka: web::Data<Modelka>,
+    slug: web::Path<String>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let db_pool = pool.get_ref().to_owned();
+    let requested_slug = slug.into_inner();
+    let query_slug = requested_slug.clone();
+
+    let maybe_post = web::block(move || {
+        use diesel::OptionalExtension;
+        let conn = db_pool.get().map_err(|err| err.to_string())?;
+
+        blogposts::v2::find_visible_by_slug(&conn, query_slug.as_str())
+            .optional()
+            .map_err(|err| err.to_string())
+    })
+    .await
+    .map_err(actix_web::Error::from)?;
+
+    match maybe_post {
+        Some(post) if post.slug != requested_slug => {
+            let mut location = String::new();
+
+            location.push_str("/blog/");
+            location.push_str(post.slug.as_str());
+
+            Ok(HttpResponse::MovedPermanently()
+                .header(header::LOCATION, location)
+                .finish())
+        }
+        Some(post) => Ok(html_response(&modelka, page::Page::blogpost(&post))),
+        None => Ok(HttpResponse::NotFound()
+            .content_type("text/html; charset=utf-8")
+            .body(page::Page::shell(http_req.path()).render(modelka.site_url.as_str()))),
+    }
+}
+
+async fn blog_route(
+    pool: web::Data<Pool>,
+    modelka: web::Data<Modelka>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let db_pool = pool.get_ref().to_owned();
+
+    let posts = web::block(move || {
+        let conn = db_pool.get().map_err(|err| err.to_string())?;
+
+        blogposts::v2::list_visible(&conn).map_err(|err| err.to_string())
+    })
+    .await
+    .map_err(actix_web::Error::from)?;
+
+    Ok(html_response(&modelka, page::Page::blog_index(&posts)))
+}
+
+/// The latest posts, and when anything public last changed
+async fn feed_posts(
+    pool: web::Data<Pool>,
+) -> Result<(Vec<blogposts::v2::Post>, f64), actix_web::Error> {
+    let db_pool = pool.get_ref().to_owned();
+
+    web::block(move || {
+        let conn = db_pool.get().map_err(|err| err.to_string())?;
+
+        let posts =
+            blogposts::v2::latest_visible(&conn, feed::LENGTH).map_err(|err| err.to_string())?;
+        let last_changed = blogposts::v2::last_changed(&conn).map_err(|err| err.to_string())?;
+
+        Ok::<_, String>((posts, last_changed))
+    })
+    .await
+    .map_err(actix_web::Error::from)
+}
+
+async fn rss_route(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    modelka: web::Data<Modelka>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (posts, last_changed) = feed_posts(pool).await?;
+
+    Ok(conditional_response(
+        &req,
+        "application/rss+xml; charset=utf-8",
+        feed::rss(modelka.site_url.as_str(), &posts),
+        last_changed,
+    ))
+}
+
+async fn atom_route(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    modelka: web::Data<Modelka>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (posts, last_changed) = feed_posts(pool).await?;
+
+    Ok(conditional_response(
+        &req,
+        "application/atom+xml; charset=utf-8",
+        feed::atom(modelka.site_url.as_str(), &posts),
+        last_changed,
+    ))
+}
+
+async fn sitemap_route(
+    req: HttpRequest,
+    pool: web::Data<Pool>,
+    modelka: web::Data<Modelka>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let db_pool = pool.get_ref().to_owned();
+
+    let (posts, last_changed) = web::block(move || {
+        let conn = db_pool.get().map_err(|err| err.to_string())?;
+
+        let posts = blogposts::v2::list_visible(&conn).map_err(|err| err.to_string())?;
+        let last_changed = blogposts::v2::last_changed(&conn).map_err(|err| err.to_string())?;
+
+        Ok::<_, String>((posts, last_changed))
+    })
+    .await
+    .map_err(actix_web::Error::from)?;
+
+    Ok(conditional_response(
+        &req,
+        "application/xml; charset=utf-8",
+        sitemap::sitemap(modelka.site_url.as_str(), &posts),
+        last_changed,
+    ))
+}
+
+async fn robots_route(modelka: web::Data<Modelka>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(sitemap::robots(
+            modelka.site_url.as_str(),
+            &modelka.robots_disallow,
+        ))
+}
+
+/// Store the files in a multipart upload, and respond with what was stored
+async fn upload_media_route(
+    http_req: HttpRequest,
+    pool: web::Data<Pool>,
+    modelka: web::Data<Modelka>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, actix_web::Error> {
+    let is_admin = http_req
+        .headers()
+        .get(graphql_schema::ADMIN_PASSWORD_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|credential| credential == modelka.admin_password);
+
+    if !is_admin {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let mut upload: Option<media::Upload> = None;
+    let mut field_count = 0;
+
+    while let Some(field) = payload.next().await {
+        let mut field = field?;
+
+        field_count += 1;
+
+        if field_count > media::MAX_FIELDS {
+            return Ok(HttpResponse::BadRequest().body("Upload has too many fields"));
+        }
+
+        let original_name = field
+            .content_disposition()
+            .and_then(|disposition| disposition.get_filename().map(|name| name.to_string()));
+
+        if original_name.is_some() && upload.is_some() {
+            return Ok(HttpResponse::BadRequest().body("Only one file can be uploaded at a time"));
+        }
+
+        let content_type = field.content_type().essence_str().to_string();
+
+        let mut bytes: Vec<u8> = Vec::new();
+
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk?;
+
+            if bytes.len() + chunk.len() > media::MAX_SIZE {
+                return Ok(HttpResponse::PayloadTooLarge().body("File is too large"));
+            }
+
+            bytes.extend_from_slice(&chunk);
+        }
+
+        // Fields without a filename are not files
+        if let Some(original_name) = original_name {
+            if !media::is_allowed(content_type.as_str()) {
+                let mut buf = String::new();
+
+                buf.push_str("Files of type ");
+                buf.push_str(content_type.as_str());
+                buf.push_str(" can not be uploaded");
+
+                return Ok(HttpResponse::BadRequest().body(buf));
+            }
+
+            if original_name.chars().count() > media::MAX_NAME_LENGTH {
+                let mut buf = String::new();
+
+                buf.push_str("File names can be at most ");
+                buf.push_str(media::MAX_NAME_LENGTH.to_string().as_str());
+                buf.push_str(" characters long");
+
+                return Ok(HttpResponse::BadRequest().body(buf));
+            }
+
+            upload = Some(media::Upload {
+                original_name,
+                content_type,
+                bytes,
+            });
+        }
+    }
+
+    let upload = match upload {
+        Some(upload) => upload,
+        None => return Ok(HttpResponse::BadRequest().body("Upload has no file")),
+    };
+
+    let db_pool = pool.get_ref().to_owned();
+    let media_dir = modelka.media_dir.clone();
+
+    let stored = web::block(move || {
+        let conn = db_pool.get().map_err(|err| err.to_string())?;
+
+        media::store(&conn, media_dir.as_str(), &upload)
+    })
+    .await
+    .map_err(actix_web::Error::from)?;
+
+    Ok(HttpResponse::Ok().json(stored))
+}
+
+async fn media_route(
+    pool: web::Data<Pool>,
+    modelka: web::Data<Modelka>,
+    filename: web::Path<String>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let filename = filename.into_inner();
+
+    if !media::is_valid_filename(filename.as_str()) {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    let db_pool = pool.get_ref().to_owned();
+    let media_dir = modelka.media_dir.clone();
+
+    let maybe_file = web::block(move || {
+        let conn = db_pool.get().map_err(|err| err.to_string())?;
+
+        match media::find_by_filename(&conn, filename.as_str()).map_err(|err| err.to_string())? {
+            Some(stored) => fs::read(media::path(media_dir.as_str(), filename.as_str()))
+                .map(|bytes| Some((stored, bytes)))
+                .map_err(|err| err.to_string()),
+            None => Ok(None),
+        }
+    })
+    .await
+    .map_err(actix_web::Error::from)?;
+
+    match maybe_file {
+        Some((stored, bytes)) => {
+            let mut etag = String::new();
+
+            etag.push('"');
+            etag.push_str(stored.filename.as_str());
+            etag.push('"');
+
+            Ok(HttpResponse::Ok()
+                .content_type(stored.content_type)
+                .header(header::CACHE_CONTROL, media::CACHE_CONTROL)
+                .header(header::ETAG, etag)
+                .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+                .header(
+                    header::CONTENT_SECURITY_POLICY,
+                    "default-src 'none'; sandbox",
+                )
+                .body(bytes))
+        }
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+#[derive(Deserialize)]
+struct ImageQuery {
+    w: Option<u32>,
+    format: Option<String>,
+}
+
+/// An image under ui/public, resized and re-encoded as the query asks
+async fn image_route(
+    modelka: web::Data<Modelka>,
+    path: web::Path<String>,
+    query: web::Query<ImageQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let source = match images::source_path(path.as_str()) {
+        Some(source) => source,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    if let Some(width) = query.w {
+        if !images::WIDTHS.contains(&width) {
+            let mut buf = String::new();
+
+            buf.push_str("Width must be one of ");
+            buf.push_str(
+                images::WIDTHS
+                    .iter()
+                    .map(|width| width.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+                    .as_str(),
+            );
+
+            return Ok(HttpResponse::BadRequest().body(buf));
+        }
+    }
+
+    let format = match &query.format {
+        Some(format_str) => match images::Format::from_extension(format_str.as_str()) {
+            Some(format) => Some(format),
+            None => return Ok(HttpResponse::BadRequest().body("Unsupported image format")),
+        },
+        None => None,
+    };
+
+    let cache_dir = modelka.image_cache_dir.clone();
+    let width = query.w;
+
+    // Resizing is slow, so it happens on the blocking thread pool rather
+    // than on the thread serving requests
+    let (bytes, format) =
+        web::block(move || images::variant(cache_dir.as_str(), source.as_path(), width, format))
+            .await
+            .map_err(actix_web::Error::from)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .header(header::CACHE_CONTROL, images::CACHE_CONTROL)
+        .body(bytes))
+}
+
+#[derive(Deserialize)]
+struct WebmentionForm {
+    source: String,
+    target: String,
+}
+
+/// Accept a webmention to verify later, as the spec asks endpoints to do
+async fn webmention_route(
+    http_req: HttpRequest,
+    pool: web::Data<Pool>,
+    modelka: web::Data<Modelka>,
+    webmentions: web::Data<webmention::Queue>,
+    form: web::Form<WebmentionForm>,
+) -> HttpResponse {
+    // Held until the mention is saved, so senders are turned away before
+    // anything is written once the worker falls behind
+    let reservation = match webmentions.reserve() {
+        Some(reservation) => reservation,
+        None => {
+            return HttpResponse::TooManyRequests()
+                .body("Too many webmentions waiting to be verified")
+        }
+    };
+
+    let db_pool = pool.get_ref().to_owned();
+    let site_url = modelka.site_url.clone();
+    let submitter = blogposts::comment::submitter(
+        modelka.comment_secret.as_str(),
+        client_address(&http_req, &modelka).as_deref().unwrap_or(""),
+    );
+    let form = form.into_inner();
+
+    let received = web::block(move || {
+        webmention::receive(
+            &db_pool,
+            site_url.as_str(),
+            form.source.as_str(),
+            form.target.as_str(),
+            submitter.as_str(),
+        )
+    })
+    .await;
+
+    match received {
+        Ok(blogposts::webmention::Receipt::Pending(webmention_id)) => {
+            reservation.push(webmention::Job::Verify(webmention_id));
+
+            HttpResponse::Accepted().finish()
+        }
+        Ok(blogposts::webmention::Receipt::AlreadyPending) => HttpResponse::Accepted().finish(),
+        Ok(blogposts::webmention::Receipt::RateLimited) => {
+            HttpResponse::TooManyRequests().body("Too many webmentions sent, try again later")
+        }
+        Err(BlockingError::Error(msg)) => HttpResponse::BadRequest().body(msg),
+        Err(BlockingError::Canceled) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+async fn highlight_css_route() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/css; charset=utf-8")
+        .body(highlight::css())
+}
+
+async fn frontend(http_req: HttpRequest, modelka: web::Data<Modelka>) -> HttpResponse {
+    html_response(&modelka, page::Page::shell(http_req.path()))
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -359,6 +952,130 @@ fn clear_terminal() {
         .expect("Failed to clear terminal");
 }
 
+////////////////////////////////////////////////////////////////////////////////
+// EXPORT //
+////////////////////////////////////////////////////////////////////////////////
+
+fn export_site(pool: &Pool, modelka: &Modelka) -> Result<(), String> {
+    let conn = pool.get().map_err(|err| err.to_string())?;
+
+    let posts = blogposts::v2::list_visible(&conn).map_err(|err| err.to_string())?;
+
+    let site = export::Site {
+        site_url: modelka.site_url.as_str(),
+        robots_disallow: &modelka.robots_disallow,
+        posts: &posts,
+        elm_js: read_elm_file().map_err(|err| err.to_string())?,
+        app_js: fs::read_to_string(ui_src("app.js")).map_err(|err| err.to_string())?,
+    };
+
+    export::write(modelka.out_dir.as_str(), &site).map_err(|err| err.to_string())?;
+
+    let mut buf = String::new();
+
+    buf.push_str("Exported ");
+    buf.push_str(posts.len().to_string().as_str());
+    buf.push_str(" posts to ");
+    buf.push_str(modelka.out_dir.as_str());
+
+    println!("{}", buf);
+
+    Ok(())
+}
+
+fn import_posts(pool: &Pool, modelka: &Modelka) -> Result<(), String> {
+    let conn = pool.get().map_err(|err| err.to_string())?;
+
+    let summary = blogposts::file::import(&conn, modelka.posts_dir.as_str())?;
+
+    let mut buf = String::new();
+
+    buf.push_str("Created ");
+    buf.push_str(summary.created.to_string().as_str());
+    buf.push_str(", updated ");
+    buf.push_str(summary.updated.to_string().as_str());
+    buf.push_str(" and left ");
+    buf.push_str(summary.unchanged.to_string().as_str());
+    buf.push_str(" posts unchanged from ");
+    buf.push_str(modelka.posts_dir.as_str());
+
+    println!("{}", buf);
+
+    Ok(())
+}
+
+fn export_posts(pool: &Pool, modelka: &Modelka) -> Result<(), String> {
+    let conn = pool.get().map_err(|err| err.to_string())?;
+
+    let count = blogposts::file::export(&conn, modelka.posts_dir.as_str())?;
+
+    let mut buf = String::new();
+
+    buf.push_str("Exported ");
+    buf.push_str(count.to_string().as_str());
+    buf.push_str(" posts to ");
+    buf.push_str(modelka.posts_dir.as_str());
+
+    println!("{}", buf);
+
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// LINKS //
+////////////////////////////////////////////////////////////////////////////////
+
+/// Print the broken links in every post, failing if there are any so that
+/// scripts can tell
+fn check_links(pool: &Pool, modelka: &Modelka) -> Result<(), String> {
+    let conn = pool.get().map_err(|err| err.to_string())?;
+
+    let reports = links::check(
+        &conn,
+        modelka.site_url.as_str(),
+        modelka.check_external_links,
+    )
+    .map_err(|err| err.to_string())?;
+
+    let mut broken_count = 0;
+
+    for report in &reports {
+        let mut buf = String::new();
+
+        buf.push_str(report.post.title.as_str());
+        buf.push_str(" (");
+        buf.push_str(report.post.path().as_str());
+        buf.push(')');
+
+        for broken in &report.broken {
+            buf.push_str("\n  ");
+            buf.push_str(broken.url.as_str());
+            buf.push_str(" : ");
+            buf.push_str(broken.reason.as_str());
+        }
+
+        println!("{}", buf);
+
+        broken_count += report.broken.len();
+    }
+
+    if broken_count == 0 {
+        println!("No broken links found");
+
+        return Ok(());
+    }
+
+    let mut buf = String::new();
+
+    buf.push_str("Found ");
+    buf.push_str(broken_count.to_string().as_str());
+    buf.push_str(" broken links in ");
+    buf.push_str(reports.len().to_string().as_str());
+    buf.push_str(" posts");
+
+    Err(buf)
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // DEV //
 ////////////////////////////////////////////////////////////////////////////////
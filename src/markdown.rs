@@ -0,0 +1,240 @@
+use crate::blogposts::slug::slugify;
+use crate::feed::escape;
+use crate::highlight;
+use ammonia::Builder;
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, Options, Parser, Tag};
+use std::collections::HashSet;
+
+/// Render post content from Markdown into HTML that is safe to put on the page
+pub fn to_html(markdown: &str) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+
+    let events: Vec<Event> = Parser::new_ext(markdown, options).collect();
+
+    let mut unsafe_html = String::new();
+    html::push_html(
+        &mut unsafe_html,
+        with_highlighted_code(with_heading_anchors(events)).into_iter(),
+    );
+
+    sanitize(unsafe_html.as_str())
+}
+
+/// The words of the post with the markup stripped, separated by single spaces
+pub fn plain_text(markdown: &str) -> String {
+    let mut text = String::new();
+    let mut in_image = false;
+
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Start(Tag::Image(..)) => in_image = true,
+            Event::End(Tag::Image(..)) => in_image = false,
+            Event::Text(chunk) | Event::Code(chunk) if !in_image => text.push_str(chunk.as_ref()),
+            Event::SoftBreak | Event::HardBreak | Event::End(_) => text.push(' '),
+            _ => {}
+        }
+    }
+
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// The text of the post with the markup stripped, cut down to about
+/// `max_chars` characters on a word boundary
+pub fn summary(markdown: &str, max_chars: usize) -> String {
+    let mut buf = String::new();
+    let mut length = 0;
+
+    for word in plain_text(markdown).split(' ') {
+        let word_length = word.chars().count();
+
+        if length + word_length + 1 > max_chars {
+            buf.push('…');
+            break;
+        }
+
+        if !buf.is_empty() {
+            buf.push(' ');
+            length += 1;
+        }
+
+        buf.push_str(word);
+        length += word_length;
+    }
+
+    buf
+}
+
+/// The address of the first image in the post, if it has any
+pub fn first_image(markdown: &str) -> Option<String> {
+    Parser::new(markdown).find_map(|event| match event {
+        Event::Start(Tag::Image(_, url, _)) => Some(url.to_string()),
+        _ => None,
+    })
+}
+
+/// Every page on another site the post links to, each only once
+pub fn external_links(markdown: &str) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+
+    for event in Parser::new(markdown) {
+        if let Event::Start(Tag::Link(_, url, _)) = event {
+            let is_external = url.starts_with("http://") || url.starts_with("https://");
+
+            if is_external && !links.iter().any(|link| link.as_str() == url.as_ref()) {
+                links.push(url.to_string());
+            }
+        }
+    }
+
+    links
+}
+
+/// Everything the post links to or shows, each only once, and whether it
+/// is shown as an image
+pub fn links_and_images(markdown: &str) -> Vec<(String, bool)> {
+    let mut found: Vec<(String, bool)> = Vec::new();
+
+    for event in Parser::new(markdown) {
+        let (url, is_image) = match event {
+            Event::Start(Tag::Link(_, url, _)) => (url, false),
+            Event::Start(Tag::Image(_, url, _)) => (url, true),
+            _ => continue,
+        };
+
+        let entry = (url.to_string(), is_image);
+
+        if !found.contains(&entry) {
+            found.push(entry);
+        }
+    }
+
+    found
+}
+
+/// Give every heading an id derived from its text, so sections can be linked to
+fn with_heading_anchors(events: Vec<Event>) -> Vec<Event> {
+    let mut used_anchors: HashSet<String> = HashSet::new();
+    let mut anchors: Vec<String> = Vec::new();
+
+    for (index, event) in events.iter().enumerate() {
+        if let Event::Start(Tag::Heading(..)) = event {
+            let base = slugify(heading_text(&events[index + 1..]).as_str());
+            let mut anchor = base.clone();
+            let mut suffix = 1;
+
+            while used_anchors.contains(&anchor) {
+                suffix += 1;
+
+                anchor = base.clone();
+                anchor.push('-');
+                anchor.push_str(suffix.to_string().as_str());
+            }
+
+            used_anchors.insert(anchor.clone());
+            anchors.push(anchor);
+        }
+    }
+
+    let mut anchors = anchors.into_iter();
+
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::Heading(level, _, _)) => {
+                let mut buf = String::new();
+
+                buf.push('<');
+                buf.push_str(level.to_string().as_str());
+                buf.push_str(" id=\"");
+                buf.push_str(anchors.next().unwrap_or_default().as_str());
+                buf.push_str("\">");
+
+                Event::Html(CowStr::from(buf))
+            }
+            Event::End(Tag::Heading(level, _, _)) => {
+                let mut buf = String::new();
+
+                buf.push_str("</");
+                buf.push_str(level.to_string().as_str());
+                buf.push_str(">\n");
+
+                Event::Html(CowStr::from(buf))
+            }
+            event => event,
+        })
+        .collect()
+}
+
+/// Replace fenced code blocks in languages with a grammar by highlighted html
+fn with_highlighted_code(events: Vec<Event>) -> Vec<Event> {
+    let mut highlighted: Vec<Event> = Vec::new();
+    let mut code_block: Option<(String, String)> = None;
+
+    for event in events {
+        match (event, &mut code_block) {
+            (Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))), None)
+                if highlight::supports(fence_language(info.as_ref()).as_str()) =>
+            {
+                code_block = Some((fence_language(info.as_ref()), String::new()));
+            }
+            (Event::Text(text), Some((_, code))) => code.push_str(text.as_ref()),
+            (Event::End(Tag::CodeBlock(_)), Some((language, code))) => {
+                let mut buf = String::new();
+
+                buf.push_str("<pre><code class=\"language-");
+                buf.push_str(escape(language).as_str());
+                buf.push_str("\">");
+                buf.push_str(
+                    highlight::highlight(language, code)
+                        .unwrap_or_else(|| escape(code))
+                        .as_str(),
+                );
+                buf.push_str("</code></pre>\n");
+
+                highlighted.push(Event::Html(CowStr::from(buf)));
+                code_block = None;
+            }
+            (event, _) => highlighted.push(event),
+        }
+    }
+
+    highlighted
+}
+
+/// The language a fence is marked with, like the `rust` of ```rust
+fn fence_language(info: &str) -> String {
+    info.split(|c: char| c.is_whitespace() || c == ',')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+fn heading_text(events: &[Event]) -> String {
+    let mut buf = String::new();
+
+    for event in events {
+        match event {
+            Event::End(Tag::Heading(..)) => break,
+            Event::Text(text) | Event::Code(text) => buf.push_str(text),
+            _ => {}
+        }
+    }
+
+    buf
+}
+
+fn sanitize(html: &str) -> String {
+    Builder::default()
+        .add_generic_attributes(&["id"])
+        .add_tag_attributes("code", &["class"])
+        .add_tag_attributes("div", &["class"])
+        .add_tag_attributes("span", &["class"])
+        .add_tag_attributes("sup", &["class"])
+        .add_tag_attributes("th", &["style"])
+        .add_tag_attributes("td", &["style"])
+        .clean(html)
+        .to_string()
+}
@@ -0,0 +1,182 @@
+use crate::blogposts::v2::now;
+use crate::db::last_insert_id;
+use crate::schema::media;
+use diesel::mysql::MysqlConnection;
+use diesel::{
+    Connection, ExpressionMethods, OptionalExtension, QueryDsl, QueryResult, RunQueryDsl,
+};
+use juniper::GraphQLObject;
+use serde_derive::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+/// The largest file an upload may contain
+pub const MAX_SIZE: usize = 20 * 1024 * 1024;
+
+/// The most fields an upload request may have, so that a request can not
+/// keep the server reading forever
+pub const MAX_FIELDS: usize = 8;
+
+/// The longest original name the media table has room for, in characters
+pub const MAX_NAME_LENGTH: usize = 256;
+
+/// Files are named after their contents, so they never change once served
+pub const CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+#[derive(Queryable, Serialize, GraphQLObject)]
+#[graphql(description = "A file uploaded to be used in posts")]
+pub struct Media {
+    pub id: i32,
+    #[graphql(description = "The name the file is stored and served under")]
+    pub filename: String,
+    #[graphql(description = "The name of the file on the computer it was uploaded from")]
+    pub original_name: String,
+    pub content_type: String,
+    #[graphql(description = "In bytes")]
+    pub size: i32,
+    #[graphql(description = "When the file was uploaded, in milliseconds since the epoch")]
+    pub created_at: f64,
+}
+
+pub struct Upload {
+    pub original_name: String,
+    pub content_type: String,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Insertable)]
+#[table_name = "media"]
+struct New<'a> {
+    filename: &'a str,
+    original_name: &'a str,
+    content_type: &'a str,
+    size: i32,
+    created_at: f64,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// HELPERS //
+////////////////////////////////////////////////////////////////////////////////
+
+/// Only media goes in posts. Anything else, like html, could run scripts on
+/// the site if it were served from it.
+pub fn is_allowed(content_type: &str) -> bool {
+    content_type.starts_with("image/")
+        || content_type.starts_with("audio/")
+        || content_type.starts_with("video/")
+        || content_type == "application/pdf"
+}
+
+/// Whether `filename` could be one this module stored, which keeps paths
+/// like `../` out of the media directory
+pub fn is_valid_filename(filename: &str) -> bool {
+    let mut parts = filename.splitn(2, '.');
+
+    let hash_is_valid = parts
+        .next()
+        .map(|hash| !hash.is_empty() && hash.chars().all(|c| c.is_ascii_hexdigit()))
+        .unwrap_or(false);
+
+    let extension_is_valid = parts
+        .next()
+        .map(|extension| extension.chars().all(|c| c.is_ascii_alphanumeric()))
+        .unwrap_or(true);
+
+    hash_is_valid && extension_is_valid
+}
+
+/// The hash of the contents, plus the extension of the original name
+fn filename(upload: &Upload) -> String {
+    let mut buf = format!("{:x}", Sha256::digest(&upload.bytes));
+
+    let extension: Option<String> = Path::new(upload.original_name.as_str())
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .filter(|extension| {
+            !extension.is_empty()
+                && extension.len() <= 10
+                && extension.chars().all(|c| c.is_ascii_alphanumeric())
+        });
+
+    if let Some(extension) = extension {
+        buf.push('.');
+        buf.push_str(extension.as_str());
+    }
+
+    buf
+}
+
+pub fn path(media_dir: &str, filename: &str) -> std::path::PathBuf {
+    Path::new(media_dir).join(filename)
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// QUERIES //
+////////////////////////////////////////////////////////////////////////////////
+
+/// Everything uploaded, newest first
+pub fn list(conn: &MysqlConnection) -> QueryResult<Vec<Media>> {
+    media::table
+        .order((media::created_at.desc(), media::id.desc()))
+        .load::<Media>(conn)
+}
+
+pub fn get(conn: &MysqlConnection, media_id: i32) -> QueryResult<Media> {
+    media::table.find(media_id).first::<Media>(conn)
+}
+
+pub fn find_by_filename(conn: &MysqlConnection, name: &str) -> QueryResult<Option<Media>> {
+    media::table
+        .filter(media::filename.eq(name))
+        .first::<Media>(conn)
+        .optional()
+}
+
+/// Write the upload to disk and record it. Uploading the same file again
+/// gives back what was stored the first time.
+pub fn store(conn: &MysqlConnection, media_dir: &str, upload: &Upload) -> Result<Media, String> {
+    let name = filename(upload);
+
+    if let Some(existing) = find_by_filename(conn, name.as_str()).map_err(|err| err.to_string())? {
+        return Ok(existing);
+    }
+
+    fs::create_dir_all(media_dir).map_err(|err| err.to_string())?;
+    fs::write(path(media_dir, name.as_str()), &upload.bytes).map_err(|err| err.to_string())?;
+
+    let new_media = New {
+        filename: name.as_str(),
+        original_name: upload.original_name.as_str(),
+        content_type: upload.content_type.as_str(),
+        size: upload.bytes.len() as i32,
+        created_at: now(),
+    };
+
+    conn.transaction(|| {
+        diesel::insert_into(media::table)
+            .values(&new_media)
+            .execute(conn)?;
+
+        let media_id = diesel::select(last_insert_id).first::<u64>(conn)?;
+
+        get(conn, media_id as i32)
+    })
+    .map_err(|err| err.to_string())
+}
+
+/// Forget the file and remove it from disk
+pub fn delete(conn: &MysqlConnection, media_dir: &str, media_id: i32) -> QueryResult<Media> {
+    conn.transaction(|| {
+        let deleted = get(conn, media_id)?;
+
+        diesel::delete(media::table.find(media_id)).execute(conn)?;
+
+        match fs::remove_file(path(media_dir, deleted.filename.as_str())) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(diesel::result::Error::RollbackTransaction)
+            }
+            _ => Ok(deleted),
+        }
+    })
+}
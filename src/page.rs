@@ -0,0 +1,183 @@
+use crate::blogposts::v2::{date_time, Post};
+use crate::feed::{escape, url, SITE_TITLE};
+use crate::markdown;
+
+const SITE_DESCRIPTION: &str = "Chadtech makes software, music and art";
+
+/// Roughly what search engines show of a meta description
+const DESCRIPTION_LENGTH: usize = 160;
+
+/// What the server renders into the html shell before the Elm app boots
+pub struct Page {
+    pub title: String,
+    pub description: String,
+    /// The canonical path of the page, relative to the root of the site
+    pub path: String,
+    pub kind: Kind,
+    pub image: Option<String>,
+    pub body: String,
+}
+
+/// The OpenGraph type of a page
+pub enum Kind {
+    Website,
+    Article,
+}
+
+impl Page {
+    /// The bare shell, for pages only the Elm app knows how to draw
+    pub fn shell(path: &str) -> Page {
+        Page {
+            title: SITE_TITLE.to_string(),
+            description: SITE_DESCRIPTION.to_string(),
+            path: path.to_string(),
+            kind: Kind::Website,
+            image: None,
+            body: String::new(),
+        }
+    }
+
+    pub fn blog_index(posts: &[Post]) -> Page {
+        let mut body = String::new();
+
+        body.push_str("<main>\n<h1>Blog</h1>\n<ul>\n");
+
+        for post in posts {
+            body.push_str("<li><a href=\"");
+            body.push_str(escape(post.path().as_str()).as_str());
+            body.push_str("\">");
+            body.push_str(escape(post.title.as_str()).as_str());
+            body.push_str("</a> ");
+            push_date(&mut body, post.date);
+            body.push_str("</li>\n");
+        }
+
+        body.push_str("</ul>\n</main>");
+
+        Page {
+            title: title("Blog"),
+            description: "Everything Chadtech has written".to_string(),
+            path: "/blog".to_string(),
+            kind: Kind::Website,
+            image: None,
+            body,
+        }
+    }
+
+    pub fn blogpost(post: &Post) -> Page {
+        let mut body = String::new();
+
+        body.push_str("<main>\n<article>\n<h1>");
+        body.push_str(escape(post.title.as_str()).as_str());
+        body.push_str("</h1>\n");
+        push_date(&mut body, post.date);
+        body.push('\n');
+        body.push_str(post.html().as_str());
+        body.push_str("</article>\n</main>");
+
+        Page {
+            title: title(post.title.as_str()),
+            description: markdown::summary(post.content.as_str(), DESCRIPTION_LENGTH),
+            path: post.path(),
+            kind: Kind::Article,
+            image: markdown::first_image(post.content.as_str()),
+            body,
+        }
+    }
+
+    pub fn render(&self, site_url: &str) -> String {
+        include_str!("./assets/index.html")
+            .replace("${title}", template_escape(self.title.as_str()).as_str())
+            .replace("${meta}", self.meta(site_url).trim_end())
+            .replace("${body}", self.body.as_str())
+    }
+
+    fn meta(&self, site_url: &str) -> String {
+        let canonical_url = url(site_url, self.path.as_str());
+        let image_url = self.image.as_ref().map(|image| {
+            if image.starts_with('/') {
+                url(site_url, image.as_str())
+            } else {
+                image.to_string()
+            }
+        });
+
+        let og_type = match self.kind {
+            Kind::Website => "website",
+            Kind::Article => "article",
+        };
+
+        let twitter_card = match image_url {
+            Some(_) => "summary_large_image",
+            None => "summary",
+        };
+
+        let mut buf = String::new();
+
+        push_meta(&mut buf, "name", "description", self.description.as_str());
+        buf.push_str("    <link rel=\"canonical\" href=\"");
+        buf.push_str(template_escape(canonical_url.as_str()).as_str());
+        buf.push_str("\">\n");
+        push_meta(&mut buf, "property", "og:title", self.title.as_str());
+        push_meta(
+            &mut buf,
+            "property",
+            "og:description",
+            self.description.as_str(),
+        );
+        push_meta(&mut buf, "property", "og:type", og_type);
+        push_meta(&mut buf, "property", "og:url", canonical_url.as_str());
+        push_meta(&mut buf, "property", "og:site_name", SITE_TITLE);
+        push_meta(&mut buf, "name", "twitter:card", twitter_card);
+        push_meta(&mut buf, "name", "twitter:title", self.title.as_str());
+        push_meta(
+            &mut buf,
+            "name",
+            "twitter:description",
+            self.description.as_str(),
+        );
+
+        if let Some(image_url) = image_url {
+            push_meta(&mut buf, "property", "og:image", image_url.as_str());
+            push_meta(&mut buf, "name", "twitter:image", image_url.as_str());
+        }
+
+        buf
+    }
+}
+
+fn title(page_title: &str) -> String {
+    let mut buf = String::new();
+
+    buf.push_str(page_title);
+    buf.push_str(" | ");
+    buf.push_str(SITE_TITLE);
+
+    buf
+}
+
+/// Escape text that goes into the shell before the body does, so nothing in
+/// it can form the placeholders that are filled in after it
+fn template_escape(text: &str) -> String {
+    escape(text).replace('$', "&#36;")
+}
+
+fn push_meta(buf: &mut String, key: &str, name: &str, content: &str) {
+    buf.push_str("    <meta ");
+    buf.push_str(key);
+    buf.push_str("=\"");
+    buf.push_str(name);
+    buf.push_str("\" content=\"");
+    buf.push_str(template_escape(content).as_str());
+    buf.push_str("\">\n");
+}
+
+fn push_date(buf: &mut String, millis: f64) {
+    let date = date_time(millis);
+
+    buf.push_str("<time datetime=\"");
+    buf.push_str(date.to_rfc3339().as_str());
+    buf.push_str("\">");
+    buf.push_str(date.format("%B %-d, %Y").to_string().as_str());
+    buf.push_str("</time>");
+}
@@ -0,0 +1,44 @@
+pub const HOME: &str = "/";
+
+pub const ELM_JS: &str = "/elm.js";
+
+pub const APP_JS: &str = "/app.js";
+
+pub const GRAPHQL: &str = "/graphql";
+
+pub const GRAPHIQL: &str = "/graphiql";
+
+pub const BLOG: &str = "/blog";
+
+pub const RSS: &str = "/feed.xml";
+
+pub const ATOM: &str = "/atom.xml";
+
+pub const SITEMAP: &str = "/sitemap.xml";
+
+pub const ROBOTS: &str = "/robots.txt";
+
+pub const HIGHLIGHT_CSS: &str = "/highlight.css";
+
+pub const MEDIA: &str = "/media";
+
+pub const WEBMENTION: &str = "/webmention";
+
+/// The paths above that links can go to. Handlers are registered under the
+/// same constants, so the link checker knows about every route the server
+/// has.
+pub const PAGES: [&str; 10] = [
+    HOME,
+    BLOG,
+    RSS,
+    ATOM,
+    SITEMAP,
+    ROBOTS,
+    HIGHLIGHT_CSS,
+    ELM_JS,
+    APP_JS,
+    GRAPHIQL,
+];
+
+/// Paths the ui has pages under
+pub const UI_PREFIXES: [&str; 2] = ["/admin", "/componentlibrary"];
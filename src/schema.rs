@@ -13,10 +13,147 @@ table! {
     blogpostv2 (id) {
         id -> Integer,
         title -> Varchar,
+        date -> Double,
+        content -> Text,
+        status -> Varchar,
+        publish_at -> Nullable<Double>,
+        slug -> Varchar,
+        content_html -> Nullable<Text>,
+        updated_at -> Double,
+        uid -> Varchar,
+        word_count -> Nullable<Integer>,
+        reading_minutes -> Nullable<Integer>,
     }
 }
 
+table! {
+    blogpostv2_deletion (post_id) {
+        post_id -> Integer,
+        deleted_at -> Double,
+    }
+}
+
+table! {
+    blogpostv2_old_slug (slug) {
+        slug -> Varchar,
+        post_id -> Integer,
+    }
+}
+
+table! {
+    blogpostv2_related (post_id, related_post_id) {
+        post_id -> Integer,
+        related_post_id -> Integer,
+        score -> Double,
+    }
+}
+
+table! {
+    blogpostv2_revision (id) {
+        id -> Integer,
+        post_id -> Integer,
+        title -> Varchar,
+        content -> Text,
+        created_at -> Double,
+    }
+}
+
+table! {
+    blogpostv2_series (post_id) {
+        post_id -> Integer,
+        series_id -> Integer,
+        position -> Integer,
+    }
+}
+
+table! {
+    blogpostv2_tag (post_id, tag_id) {
+        post_id -> Integer,
+        tag_id -> Integer,
+    }
+}
+
+table! {
+    comment (id) {
+        id -> Integer,
+        post_id -> Integer,
+        author_name -> Varchar,
+        body -> Text,
+        status -> Varchar,
+        submitter -> Varchar,
+        created_at -> Double,
+    }
+}
+
+table! {
+    media (id) {
+        id -> Integer,
+        filename -> Varchar,
+        original_name -> Varchar,
+        content_type -> Varchar,
+        size -> Integer,
+        created_at -> Double,
+    }
+}
+
+table! {
+    series (id) {
+        id -> Integer,
+        title -> Varchar,
+        description -> Text,
+    }
+}
+
+table! {
+    tag (id) {
+        id -> Integer,
+        name -> Varchar,
+    }
+}
+
+table! {
+    webmention (id) {
+        id -> Integer,
+        post_id -> Integer,
+        source -> Varchar,
+        target -> Varchar,
+        status -> Varchar,
+        created_at -> Double,
+        verified_at -> Nullable<Double>,
+        submitter -> Varchar,
+    }
+}
+
+table! {
+    webmention_sent (post_id) {
+        post_id -> Integer,
+        sent_at -> Double,
+    }
+}
+
+joinable!(blogpostv2_old_slug -> blogpostv2 (post_id));
+joinable!(blogpostv2_revision -> blogpostv2 (post_id));
+joinable!(blogpostv2_series -> blogpostv2 (post_id));
+joinable!(blogpostv2_series -> series (series_id));
+joinable!(blogpostv2_tag -> blogpostv2 (post_id));
+joinable!(blogpostv2_tag -> tag (tag_id));
+joinable!(comment -> blogpostv2 (post_id));
+joinable!(webmention -> blogpostv2 (post_id));
+joinable!(webmention_sent -> blogpostv2 (post_id));
+
 allow_tables_to_appear_in_same_query!(
     analytics_event,
     blogpostv2,
+    blogpostv2_deletion,
+    blogpostv2_old_slug,
+    blogpostv2_related,
+    blogpostv2_revision,
+    blogpostv2_series,
+    blogpostv2_tag,
+    comment,
+    media,
+    series,
+    tag,
+    webmention,
+    webmention_sent,
 );
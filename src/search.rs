@@ -0,0 +1,394 @@
+use crate::blogposts::v2::Post;
+use crate::feed::escape;
+use crate::kontext::Kontext;
+use crate::markdown;
+use juniper::GraphQLObject;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// How many more times a word in the title counts than one in the content
+pub const TITLE_WEIGHT: f64 = 3.0;
+
+/// How many words of content a snippet shows around the first match
+const SNIPPET_WORDS: usize = 30;
+
+/// How long an index is used before it is built again. Posts imported from
+/// the command line change the database without the server knowing, so this
+/// is how long they can take to show up in searches.
+const MAX_AGE: Duration = Duration::from_secs(5 * 60);
+
+#[derive(GraphQLObject)]
+#[graphql(
+    Context = Kontext,
+    Scalar = juniper::DefaultScalarValue,
+    description = "A blog post matching a search"
+)]
+pub struct SearchResult {
+    pub post: Post,
+    pub score: f64,
+    #[graphql(
+        description = "Part of the post around the matches, as HTML with the matches in <mark>"
+    )]
+    pub snippet: String,
+}
+
+/// An in memory index over the title and content of every post
+pub struct Index {
+    documents: Vec<Document>,
+    document_frequency: HashMap<String, usize>,
+}
+
+struct Document {
+    post_id: i32,
+    title_terms: HashMap<String, u32>,
+    content_terms: HashMap<String, u32>,
+    text: String,
+}
+
+pub struct Hit {
+    pub post_id: i32,
+    pub score: f64,
+    pub snippet: String,
+}
+
+/// The index shared by every request. Anything that changes a post
+/// invalidates it, and the next search builds it again.
+#[derive(Clone, Default)]
+pub struct SharedIndex(Arc<RwLock<Cache>>);
+
+#[derive(Default)]
+struct Cache {
+    index: Option<(Arc<Index>, Instant)>,
+    /// Counts invalidations, so that an index built from posts that changed
+    /// while it was being built is not kept
+    generation: u64,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// INDEX //
+////////////////////////////////////////////////////////////////////////////////
+
+impl Index {
+    pub fn build(posts: &[Post]) -> Index {
+        let documents: Vec<Document> = posts
+            .iter()
+            .map(|post| {
+                let text = markdown::plain_text(post.content.as_str());
+
+                Document {
+                    post_id: post.id,
+                    title_terms: term_counts(post.title.as_str()),
+                    content_terms: term_counts(text.as_str()),
+                    text,
+                }
+            })
+            .collect();
+
+        let mut document_frequency: HashMap<String, usize> = HashMap::new();
+
+        for document in &documents {
+            let terms: HashSet<&String> = document
+                .title_terms
+                .keys()
+                .chain(document.content_terms.keys())
+                .collect();
+
+            for term in terms {
+                *document_frequency.entry(term.to_string()).or_insert(0) += 1;
+            }
+        }
+
+        Index {
+            documents,
+            document_frequency,
+        }
+    }
+
+    /// Every post matching any word of `query`, best match first
+    pub fn search(&self, query: &str) -> Vec<Hit> {
+        let mut terms = tokenize(query);
+        terms.sort();
+        terms.dedup();
+
+        let document_count = self.documents.len() as f64;
+
+        let mut hits: Vec<Hit> = self
+            .documents
+            .iter()
+            .filter_map(|document| {
+                let mut score = 0.0;
+
+                for term in &terms {
+                    let title_count = *document.title_terms.get(term).unwrap_or(&0) as f64;
+                    let content_count = *document.content_terms.get(term).unwrap_or(&0) as f64;
+
+                    if title_count + content_count > 0.0 {
+                        let frequency = *self.document_frequency.get(term).unwrap_or(&1) as f64;
+                        let rarity = (1.0 + document_count / frequency).ln();
+
+                        let content_score = if content_count > 0.0 {
+                            1.0 + content_count.ln()
+                        } else {
+                            0.0
+                        };
+
+                        score += rarity * (TITLE_WEIGHT * title_count + content_score);
+                    }
+                }
+
+                if score > 0.0 {
+                    Some(Hit {
+                        post_id: document.post_id,
+                        score,
+                        snippet: snippet(document.text.as_str(), &terms),
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        hits.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        hits
+    }
+}
+
+impl SharedIndex {
+    pub fn get_or_build<E>(
+        &self,
+        build: impl FnOnce() -> Result<Index, E>,
+    ) -> Result<Arc<Index>, E> {
+        let mut generation = None;
+
+        if let Ok(cache) = self.0.read() {
+            if let Some((index, built_at)) = cache.index.as_ref() {
+                if built_at.elapsed() < MAX_AGE {
+                    return Ok(index.clone());
+                }
+            }
+
+            generation = Some(cache.generation);
+        }
+
+        let index = Arc::new(build()?);
+
+        if let Ok(mut cache) = self.0.write() {
+            if generation == Some(cache.generation) {
+                cache.index = Some((index.clone(), Instant::now()));
+            }
+        }
+
+        Ok(index)
+    }
+
+    pub fn invalidate(&self) {
+        if let Ok(mut cache) = self.0.write() {
+            cache.index = None;
+            cache.generation += 1;
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// HELPERS //
+////////////////////////////////////////////////////////////////////////////////
+
+/// Lower cased words, split on anything that is not a letter or a number
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+fn term_counts(text: &str) -> HashMap<String, u32> {
+    let mut counts: HashMap<String, u32> = HashMap::new();
+
+    for term in tokenize(text) {
+        *counts.entry(term).or_insert(0) += 1;
+    }
+
+    counts
+}
+
+/// A window of `text` around the first word matching one of `terms`, with
+/// every matching word wrapped in <mark>
+fn snippet(text: &str, terms: &[String]) -> String {
+    let words: Vec<&str> = text.split_whitespace().collect();
+
+    let matches = |word: &str| tokenize(word).iter().any(|token| terms.contains(token));
+
+    let first_match = words.iter().position(|word| matches(word)).unwrap_or(0);
+    let start = first_match.saturating_sub(SNIPPET_WORDS / 3);
+    let end = (start + SNIPPET_WORDS).min(words.len());
+
+    let mut buf = String::new();
+
+    if start > 0 {
+        buf.push_str("… ");
+    }
+
+    for (index, word) in words[start..end].iter().enumerate() {
+        if index > 0 {
+            buf.push(' ');
+        }
+
+        if matches(word) {
+            buf.push_str("<mark>");
+            buf.push_str(escape(word).as_str());
+            buf.push_str("</mark>");
+        } else {
+            buf.push_str(escape(word).as_str());
+        }
+    }
+
+    if end < words.len() {
+        buf.push_str(" …");
+    }
+
+    buf
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// TESTS //
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blogposts::v2::Status;
+
+    fn post(id: i32, title: &str, content: &str) -> Post {
+        Post {
+            id,
+            title: title.to_string(),
+            date: 0.0,
+            content: content.to_string(),
+            status: Status::Published,
+            publish_at: None,
+            slug: id.to_string(),
+            content_html: None,
+            updated_at: 0.0,
+            uid: id.to_string(),
+            word_count: None,
+            reading_minutes: None,
+        }
+    }
+
+    fn post_ids(hits: &[Hit]) -> Vec<i32> {
+        hits.iter().map(|hit| hit.post_id).collect()
+    }
+
+    #[test]
+    fn tokenize_lower_cases_and_splits_on_punctuation() {
+        assert_eq!(
+            tokenize("Rust's  borrow-checker, 2018!"),
+            vec!["rust", "s", "borrow", "checker", "2018"]
+        );
+    }
+
+    #[test]
+    fn tokenize_keeps_letters_outside_ascii() {
+        assert_eq!(tokenize("Größe café"), vec!["größe", "café"]);
+        assert!(tokenize("--- !!").is_empty());
+    }
+
+    #[test]
+    fn search_ranks_title_matches_above_content_matches() {
+        let index = Index::build(&[
+            post(1, "Cooking", "Notes on elm and other trees"),
+            post(2, "Elm", "Notes on a language"),
+            post(3, "Gardening", "Nothing relevant"),
+        ]);
+
+        assert_eq!(post_ids(&index.search("elm")), vec![2, 1]);
+    }
+
+    #[test]
+    fn search_ranks_rare_words_above_common_ones() {
+        let index = Index::build(&[
+            post(1, "One", "common words here"),
+            post(2, "Two", "common words and a rare one"),
+            post(3, "Three", "common words again"),
+        ]);
+
+        let hits = index.search("common rare");
+
+        assert_eq!(hits.first().map(|hit| hit.post_id), Some(2));
+        assert_eq!(hits.len(), 3);
+    }
+
+    #[test]
+    fn search_marks_matches_in_the_snippet() {
+        let index = Index::build(&[post(1, "Title", "Some <b>bold</b> elm here")]);
+
+        let hits = index.search("ELM");
+
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].snippet.contains("<mark>elm</mark>"));
+        assert!(!hits[0].snippet.contains("<b>"));
+    }
+
+    #[test]
+    fn search_without_matches_is_empty() {
+        let index = Index::build(&[post(1, "Title", "Content")]);
+
+        assert!(index.search("missing").is_empty());
+        assert!(index.search("").is_empty());
+    }
+
+    #[test]
+    fn shared_index_is_built_once_until_invalidated() {
+        let shared = SharedIndex::default();
+        let mut builds = 0;
+
+        for _ in 0..2 {
+            shared
+                .get_or_build::<()>(|| {
+                    builds += 1;
+                    Ok(Index::build(&[]))
+                })
+                .unwrap();
+        }
+
+        assert_eq!(builds, 1);
+
+        shared.invalidate();
+        shared
+            .get_or_build::<()>(|| {
+                builds += 1;
+                Ok(Index::build(&[]))
+            })
+            .unwrap();
+
+        assert_eq!(builds, 2);
+    }
+
+    #[test]
+    fn shared_index_drops_an_index_invalidated_while_building() {
+        let shared = SharedIndex::default();
+
+        let stale = shared
+            .get_or_build::<()>(|| {
+                // A post changes while the old posts are being indexed
+                shared.invalidate();
+                Ok(Index::build(&[post(1, "Old", "old")]))
+            })
+            .unwrap();
+
+        assert_eq!(post_ids(&stale.search("old")), vec![1]);
+
+        let fresh = shared
+            .get_or_build::<()>(|| Ok(Index::build(&[post(1, "New", "new")])))
+            .unwrap();
+
+        assert!(fresh.search("old").is_empty());
+        assert_eq!(post_ids(&fresh.search("new")), vec![1]);
+    }
+}
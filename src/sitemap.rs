@@ -0,0 +1,65 @@
+use crate::blogposts::v2::{date_time, Post};
+use crate::feed::{escape, last_updated, url};
+
+/// Paths crawlers are asked to stay out of, unless configured otherwise
+pub const DEFAULT_DISALLOWED: [&str; 3] = ["/admin", "/graphiql", "/componentlibrary"];
+
+pub fn sitemap(site_url: &str, posts: &[Post]) -> String {
+    let site_updated = last_updated(posts);
+
+    let mut buf = String::new();
+
+    buf.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    buf.push_str("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+    push_url(&mut buf, url(site_url, "/").as_str(), site_updated);
+    push_url(&mut buf, url(site_url, "/blog").as_str(), site_updated);
+
+    for post in posts {
+        push_url(
+            &mut buf,
+            url(site_url, post.path().as_str()).as_str(),
+            post.changed_at(),
+        );
+    }
+
+    buf.push_str("</urlset>\n");
+
+    buf
+}
+
+pub fn robots(site_url: &str, disallowed: &[String]) -> String {
+    let mut buf = String::new();
+
+    buf.push_str("User-agent: *\n");
+
+    for path in disallowed {
+        buf.push_str("Disallow: ");
+        buf.push_str(path.as_str());
+        buf.push('\n');
+    }
+
+    buf.push_str("\nSitemap: ");
+    buf.push_str(url(site_url, "/sitemap.xml").as_str());
+    buf.push('\n');
+
+    buf
+}
+
+fn push_url(buf: &mut String, location: &str, last_modified: f64) {
+    buf.push_str("<url>\n<loc>");
+    buf.push_str(escape(location).as_str());
+    buf.push_str("</loc>\n");
+
+    if last_modified > 0.0 {
+        buf.push_str("<lastmod>");
+        buf.push_str(
+            date_time(last_modified)
+                .format("%Y-%m-%d")
+                .to_string()
+                .as_str(),
+        );
+        buf.push_str("</lastmod>\n");
+    }
+
+    buf.push_str("</url>\n");
+}
@@ -0,0 +1,967 @@
+use crate::blogposts::{v2, webmention};
+use crate::db::Pool;
+use crate::feed;
+use crate::markdown;
+use std::io::{self, Read};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use url::{Host, Url};
+
+/// How much of a page is read before giving up on it
+const MAX_PAGE_SIZE: u64 = 1024 * 1024;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+const MAX_REDIRECTS: u32 = 5;
+
+/// How many jobs can wait for the worker before mentions are turned away.
+/// Verifying one can take as long as `TIMEOUT`.
+const MAX_WAITING: usize = 100;
+
+/// How often the worker looks for scheduled posts that went up, when
+/// nothing else wakes it
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Work that needs other sites, so it is done in the background rather
+/// than while someone waits on a response
+pub enum Job {
+    /// Check that the source of a received mention links to its target
+    Verify(i32),
+    /// Let the pages linked to by every visible post that changed since it
+    /// last did know that the post links to them
+    NotifyUnsent,
+}
+
+#[derive(Clone)]
+pub struct Queue {
+    sender: Sender<Job>,
+    /// Jobs pushed that the worker has not started on yet
+    waiting: Arc<AtomicUsize>,
+}
+
+/// Room in the queue for one job, held while what the job works on is saved,
+/// and given back if the job is never pushed
+pub struct Reservation {
+    queue: Option<Queue>,
+}
+
+/// Fetches pages on other sites. Every address a host resolves to is
+/// checked before connecting to it, and redirects are followed one at a time
+/// so the host of each one is checked too.
+struct Client {
+    agent: ureq::Agent,
+}
+
+struct Tag {
+    name: String,
+    attributes: Vec<(String, String)>,
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// QUEUE //
+////////////////////////////////////////////////////////////////////////////////
+
+impl Queue {
+    pub fn push(&self, job: Job) {
+        self.waiting.fetch_add(1, Ordering::SeqCst);
+
+        // The worker only goes away with the whole server
+        let _ = self.sender.send(job);
+    }
+
+    /// Room for one more job, unless too many are waiting already
+    pub fn reserve(&self) -> Option<Reservation> {
+        if self.waiting.fetch_add(1, Ordering::SeqCst) >= MAX_WAITING {
+            self.waiting.fetch_sub(1, Ordering::SeqCst);
+
+            return None;
+        }
+
+        Some(Reservation {
+            queue: Some(self.clone()),
+        })
+    }
+}
+
+impl Reservation {
+    pub fn push(mut self, job: Job) {
+        if let Some(queue) = self.queue.take() {
+            let _ = queue.sender.send(job);
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if let Some(queue) = &self.queue {
+            queue.waiting.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Start the thread that works through jobs one at a time, in the order
+/// they were pushed
+pub fn start(pool: Pool, site_url: String) -> Queue {
+    let (sender, receiver) = channel::<Job>();
+    let waiting = Arc::new(AtomicUsize::new(0));
+    let worker_waiting = waiting.clone();
+
+    thread::spawn(move || {
+        let client = Client::new(is_public);
+
+        // Jobs do not outlast the server, so mentions it was verifying when
+        // it stopped are verified again
+        if let Err(err) = verify_pending(&pool, &client) {
+            log::error!("Could not verify pending webmentions : {}", err);
+        }
+
+        // Posts can go up while the server is down, so the first check is
+        // right away
+        let mut checked_at: Option<Instant> = None;
+
+        loop {
+            let until_check = checked_at
+                .map(|checked_at| CHECK_INTERVAL.saturating_sub(checked_at.elapsed()))
+                .unwrap_or_default();
+
+            let job = if until_check.is_zero() {
+                Job::NotifyUnsent
+            } else {
+                match receiver.recv_timeout(until_check) {
+                    Ok(job) => {
+                        worker_waiting.fetch_sub(1, Ordering::SeqCst);
+                        job
+                    }
+                    Err(RecvTimeoutError::Timeout) => Job::NotifyUnsent,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            };
+
+            if let Job::NotifyUnsent = job {
+                checked_at = Some(Instant::now());
+            }
+
+            if let Err(err) = run(&pool, &client, site_url.as_str(), job) {
+                log::error!("Webmention job failed : {}", err);
+            }
+        }
+    });
+
+    Queue { sender, waiting }
+}
+
+fn verify_pending(pool: &Pool, client: &Client) -> Result<(), String> {
+    let pending_ids = {
+        let conn = pool.get().map_err(|err| err.to_string())?;
+
+        webmention::pending_ids(&conn).map_err(|err| err.to_string())?
+    };
+
+    for webmention_id in pending_ids {
+        if let Err(err) = run(pool, client, "", Job::Verify(webmention_id)) {
+            log::error!("Webmention job failed : {}", err);
+        }
+    }
+
+    Ok(())
+}
+
+fn run(pool: &Pool, client: &Client, site_url: &str, job: Job) -> Result<(), String> {
+    match job {
+        Job::Verify(webmention_id) => {
+            let mention = {
+                let conn = pool.get().map_err(|err| err.to_string())?;
+
+                webmention::get(&conn, webmention_id).map_err(|err| err.to_string())?
+            };
+
+            let is_verified = links_to(client, mention.source.as_str(), mention.target.as_str())?;
+
+            let conn = pool.get().map_err(|err| err.to_string())?;
+
+            webmention::set_verified(&conn, webmention_id, is_verified)
+                .map(|_| ())
+                .map_err(|err| err.to_string())
+        }
+        Job::NotifyUnsent => {
+            let started_at = v2::now();
+
+            let posts = {
+                let conn = pool.get().map_err(|err| err.to_string())?;
+
+                webmention::unsent_posts(&conn).map_err(|err| err.to_string())?
+            };
+
+            for post in posts {
+                let source = feed::url(site_url, post.path().as_str());
+
+                for target in markdown::external_links(post.content.as_str()) {
+                    if let Err(err) = notify(client, source.as_str(), target.as_str()) {
+                        log::warn!("Could not send webmention to {} : {}", target, err);
+                    }
+                }
+
+                let conn = pool.get().map_err(|err| err.to_string())?;
+
+                webmention::set_sent(&conn, post.id, started_at).map_err(|err| err.to_string())?;
+            }
+
+            Ok(())
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// RECEIVING //
+////////////////////////////////////////////////////////////////////////////////
+
+/// Record a mention sent to the endpoint by `submitter`. The target has to
+/// be a post readers can see on this site.
+pub fn receive(
+    pool: &Pool,
+    site_url: &str,
+    source: &str,
+    target: &str,
+    submitter: &str,
+) -> Result<webmention::Receipt, String> {
+    let source_url = Url::parse(source).map_err(|_| "Source is not a valid URL".to_string())?;
+
+    if !is_fetchable(&source_url) {
+        return Err("Source must be a public http or https URL".to_string());
+    }
+
+    if source == target {
+        return Err("Source and target must be different".to_string());
+    }
+
+    let post_slug = match target_slug(site_url, target) {
+        Some(post_slug) => post_slug,
+        None => return Err("Target is not a post on this site".to_string()),
+    };
+
+    let conn = pool.get().map_err(|err| err.to_string())?;
+
+    let post = v2::find_visible_by_slug(&conn, post_slug).map_err(|err| match err {
+        diesel::result::Error::NotFound => "Target is not a post on this site".to_string(),
+        other => other.to_string(),
+    })?;
+
+    webmention::receive(&conn, post.id, source, target, submitter).map_err(|err| err.to_string())
+}
+
+/// The slug in a target like `https://site/blog/slug`
+fn target_slug<'a>(site_url: &str, target: &'a str) -> Option<&'a str> {
+    let path = target.strip_prefix(site_url)?.strip_prefix("/blog/")?;
+    let path = path.split(['?', '#']).next()?;
+    let path = path.strip_suffix('/').unwrap_or(path);
+
+    if path.is_empty() || path.contains('/') {
+        None
+    } else {
+        Some(path)
+    }
+}
+
+/// Whether the source still links to the target. Sources that are gone or
+/// refuse to be read do not, but a source that can not be reached right now
+/// is an error, so that what is already known about it stays.
+fn links_to(client: &Client, source: &str, target: &str) -> Result<bool, String> {
+    let response = client.get(source)?;
+
+    if response.status() >= 500 {
+        return Err(unexpected_status(&response));
+    }
+
+    if response.status() >= 400 {
+        return Ok(false);
+    }
+
+    let source_url = Url::parse(response.get_url()).map_err(|err| err.to_string())?;
+    let is_html = response.content_type() == "text/html";
+    let body = read_body(response)?;
+
+    if !is_html {
+        return Ok(body.contains(target));
+    }
+
+    let target_url = match Url::parse(target) {
+        Ok(target_url) => target_url,
+        Err(_) => return Ok(false),
+    };
+
+    Ok(tags(body.as_str()).iter().any(|tag| {
+        tag.attribute("href")
+            .and_then(|href| source_url.join(href).ok())
+            .is_some_and(|href| href == target_url)
+    }))
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// SENDING //
+////////////////////////////////////////////////////////////////////////////////
+
+/// Tell the page at `target` that `source` links to it, if it takes
+/// webmentions
+fn notify(client: &Client, source: &str, target: &str) -> Result<(), String> {
+    let endpoint = match discover(client, target)? {
+        Some(endpoint) => endpoint,
+        None => return Ok(()),
+    };
+
+    // The client checks where the endpoint is when connecting to it
+    if !is_http(&endpoint) {
+        return Err("Endpoint is not an http or https URL".to_string());
+    }
+
+    client
+        .agent
+        .post(endpoint.as_str())
+        .send_form(&[("source", source), ("target", target)])
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+/// Where the page at `target` takes webmentions, going by its Link headers
+/// first and its html after that
+fn discover(client: &Client, target: &str) -> Result<Option<Url>, String> {
+    let response = client.get(target)?;
+
+    if response.status() >= 400 {
+        return Err(unexpected_status(&response));
+    }
+
+    let base = Url::parse(response.get_url()).map_err(|err| err.to_string())?;
+
+    let from_header = response
+        .all("link")
+        .into_iter()
+        .find_map(endpoint_in_link_header)
+        .map(|href| href.to_string());
+
+    if let Some(href) = from_header {
+        return Ok(base.join(href.as_str()).ok());
+    }
+
+    if response.content_type() != "text/html" {
+        return Ok(None);
+    }
+
+    let body = read_body(response)?;
+
+    Ok(tags(body.as_str())
+        .iter()
+        .filter(|tag| tag.name == "link" || tag.name == "a")
+        .filter(|tag| tag.attribute("rel").is_some_and(has_webmention_rel))
+        .find_map(|tag| tag.attribute("href"))
+        .and_then(|href| base.join(href).ok()))
+}
+
+/// The address in a header like `<https://example.com/wm>; rel="webmention"`
+fn endpoint_in_link_header(header: &str) -> Option<&str> {
+    header.split(',').find_map(|link| {
+        let mut parts = link.split(';');
+
+        let href = parts.next()?.trim().strip_prefix('<')?.strip_suffix('>')?;
+
+        let is_webmention = parts
+            .filter_map(|param| param.split_once('='))
+            .any(|(name, value)| {
+                name.trim().eq_ignore_ascii_case("rel")
+                    && has_webmention_rel(value.trim().trim_matches('"'))
+            });
+
+        if is_webmention {
+            Some(href)
+        } else {
+            None
+        }
+    })
+}
+
+fn has_webmention_rel(rel: &str) -> bool {
+    rel.split_whitespace()
+        .any(|value| value.eq_ignore_ascii_case("webmention"))
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// CLIENT //
+////////////////////////////////////////////////////////////////////////////////
+
+impl Client {
+    fn new(is_allowed: fn(IpAddr) -> bool) -> Client {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(TIMEOUT)
+            .redirects(0)
+            .resolver(move |netloc: &str| {
+                let addresses: Vec<SocketAddr> = netloc.to_socket_addrs()?.collect();
+
+                // A name with any address that is not allowed could be
+                // connected to at that address, so none of them are used
+                if addresses.iter().all(|address| is_allowed(address.ip())) {
+                    Ok(addresses)
+                } else {
+                    Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        "Host is not at a public address",
+                    ))
+                }
+            })
+            .build();
+
+        Client { agent }
+    }
+
+    /// GET the page at `url`, following redirects to other http or https
+    /// pages. Responses with an error status are given back like any other.
+    fn get(&self, url: &str) -> Result<ureq::Response, String> {
+        let mut url = Url::parse(url).map_err(|err| err.to_string())?;
+
+        for _ in 0..=MAX_REDIRECTS {
+            if !is_http(&url) {
+                return Err("Only http and https pages are fetched".to_string());
+            }
+
+            let response = match self.agent.get(url.as_str()).call() {
+                Ok(response) | Err(ureq::Error::Status(_, response)) => response,
+                Err(err) => return Err(err.to_string()),
+            };
+
+            let location = match response.header("location") {
+                Some(location) if (300..400).contains(&response.status()) => location,
+                _ => return Ok(response),
+            };
+
+            url = url.join(location).map_err(|err| err.to_string())?;
+        }
+
+        Err("Too many redirects".to_string())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// HELPERS //
+////////////////////////////////////////////////////////////////////////////////
+
+/// Only pages out on the internet are fetched, so that mentions can not be
+/// used to make the server request things on its own network. Hosts given
+/// by name are checked once they resolve, by the client.
+fn is_fetchable(url: &Url) -> bool {
+    if !is_http(url) {
+        return false;
+    }
+
+    match url.host() {
+        Some(Host::Domain(domain)) => domain != "localhost" && !domain.ends_with(".localhost"),
+        Some(Host::Ipv4(ip)) => is_public(IpAddr::V4(ip)),
+        Some(Host::Ipv6(ip)) => is_public(IpAddr::V6(ip)),
+        None => false,
+    }
+}
+
+fn is_http(url: &Url) -> bool {
+    url.scheme() == "http" || url.scheme() == "https"
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+
+            // 100.64.0.0/10 is shared by carrier grade NATs
+            let is_shared = first == 100 && (second & 0b1100_0000) == 0b0100_0000;
+
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || is_shared)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(mapped) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(mapped));
+            }
+
+            let first_segment = ip.segments()[0];
+
+            // fc00::/7 is for unique local addresses, and fe80::/10 is link
+            // local
+            let is_unique_local = (first_segment & 0xfe00) == 0xfc00;
+            let is_link_local = (first_segment & 0xffc0) == 0xfe80;
+
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || is_unique_local
+                || is_link_local)
+        }
+    }
+}
+
+fn unexpected_status(response: &ureq::Response) -> String {
+    let mut buf = String::new();
+
+    buf.push_str(response.get_url());
+    buf.push_str(" responded with ");
+    buf.push_str(response.status().to_string().as_str());
+
+    buf
+}
+
+fn read_body(response: ureq::Response) -> Result<String, String> {
+    let mut bytes: Vec<u8> = Vec::new();
+
+    response
+        .into_reader()
+        .take(MAX_PAGE_SIZE)
+        .read_to_end(&mut bytes)
+        .map_err(|err| err.to_string())?;
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+impl Tag {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// The opening tags in a page, with their attributes. Only as much html as
+/// finding links needs is understood.
+fn tags(html: &str) -> Vec<Tag> {
+    let mut tags: Vec<Tag> = Vec::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+
+        if let Some(comment) = rest.strip_prefix("!--") {
+            rest = comment
+                .find("-->")
+                .map(|end| &comment[end + 3..])
+                .unwrap_or("");
+            continue;
+        }
+
+        let name_length = rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len());
+
+        if name_length == 0 {
+            continue;
+        }
+
+        let name = rest[..name_length].to_ascii_lowercase();
+        rest = &rest[name_length..];
+
+        let mut attributes: Vec<(String, String)> = Vec::new();
+
+        loop {
+            rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+
+            if rest.is_empty() || rest.starts_with('>') {
+                break;
+            }
+
+            let attribute_length = rest
+                .find(|c: char| c.is_whitespace() || c == '=' || c == '>' || c == '/')
+                .unwrap_or(rest.len())
+                .max(1);
+
+            let attribute = rest[..attribute_length].to_ascii_lowercase();
+            rest = rest[attribute_length..].trim_start();
+
+            let value = match rest.strip_prefix('=') {
+                Some(after_equals) => {
+                    let after_equals = after_equals.trim_start();
+
+                    match after_equals.chars().next() {
+                        Some(quote) if quote == '"' || quote == '\'' => {
+                            let inside = &after_equals[1..];
+                            let end = inside.find(quote).unwrap_or(inside.len());
+
+                            rest = inside.get(end + 1..).unwrap_or("");
+                            &inside[..end]
+                        }
+                        _ => {
+                            let end = after_equals
+                                .find(|c: char| c.is_whitespace() || c == '>')
+                                .unwrap_or(after_equals.len());
+
+                            rest = &after_equals[end..];
+                            &after_equals[..end]
+                        }
+                    }
+                }
+                None => "",
+            };
+
+            attributes.push((attribute, unescape(value)));
+        }
+
+        tags.push(Tag { name, attributes });
+    }
+
+    tags
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// TESTS //
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{Ipv4Addr, Ipv6Addr, TcpListener};
+    use std::thread::JoinHandle;
+
+    /// Stand in servers listen on 127.0.0.1, and 127.0.0.2 plays a host on
+    /// the server's own network
+    fn is_allowed_in_tests(ip: IpAddr) -> bool {
+        ip == IpAddr::V4(Ipv4Addr::LOCALHOST)
+    }
+
+    fn listen(ip: &str) -> (TcpListener, String) {
+        let listener = TcpListener::bind((ip, 0)).unwrap();
+
+        let mut buf = String::new();
+
+        buf.push_str("http://");
+        buf.push_str(listener.local_addr().unwrap().to_string().as_str());
+
+        (listener, buf)
+    }
+
+    /// Answer one request with each of `responses`, giving back the requests
+    /// that were made
+    fn serve(listener: TcpListener, responses: Vec<String>) -> JoinHandle<Vec<String>> {
+        thread::spawn(move || {
+            let mut requests: Vec<String> = Vec::new();
+
+            for response in responses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request = String::new();
+                let mut content_length = 0;
+
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+
+                    request.push_str(line.as_str());
+
+                    if line == "\r\n" || line.is_empty() {
+                        break;
+                    }
+                }
+
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                request.push_str(String::from_utf8_lossy(&body).as_ref());
+
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                requests.push(request);
+            }
+
+            requests
+        })
+    }
+
+    fn response(status: &str, headers: &[(&str, &str)], body: &str) -> String {
+        let mut buf = String::new();
+
+        buf.push_str("HTTP/1.1 ");
+        buf.push_str(status);
+        buf.push_str("\r\nConnection: close\r\nContent-Length: ");
+        buf.push_str(body.len().to_string().as_str());
+        buf.push_str("\r\n");
+
+        for (name, value) in headers {
+            buf.push_str(name);
+            buf.push_str(": ");
+            buf.push_str(value);
+            buf.push_str("\r\n");
+        }
+
+        buf.push_str("\r\n");
+        buf.push_str(body);
+
+        buf
+    }
+
+    fn html(body: &str) -> String {
+        response("200 OK", &[("Content-Type", "text/html")], body)
+    }
+
+    #[test]
+    fn client_refuses_hosts_on_the_servers_network() {
+        let (listener, url) = listen("127.0.0.1");
+
+        let _server = serve(listener, vec![html("secret")]);
+        let err = Client::new(is_public).get(url.as_str()).unwrap_err();
+
+        assert!(err.contains("not at a public address"), "{}", err);
+    }
+
+    #[test]
+    fn client_refuses_redirects_to_hosts_on_the_servers_network() {
+        let (internal_listener, internal_url) = listen("127.0.0.2");
+        let (listener, url) = listen("127.0.0.1");
+
+        let _internal = serve(internal_listener, vec![html("secret")]);
+        let server = serve(
+            listener,
+            vec![response(
+                "302 Found",
+                &[("Location", internal_url.as_str())],
+                "",
+            )],
+        );
+
+        let err = Client::new(is_allowed_in_tests)
+            .get(url.as_str())
+            .unwrap_err();
+
+        assert!(err.contains("not at a public address"), "{}", err);
+        assert_eq!(server.join().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn client_follows_redirects_one_at_a_time() {
+        let (listener, url) = listen("127.0.0.1");
+
+        let server = serve(
+            listener,
+            vec![
+                response("301 Moved Permanently", &[("Location", "/moved")], ""),
+                html("here"),
+            ],
+        );
+
+        let response = Client::new(is_allowed_in_tests).get(url.as_str()).unwrap();
+
+        assert!(response.get_url().ends_with("/moved"));
+        assert_eq!(read_body(response).unwrap(), "here");
+
+        let requests = server.join().unwrap();
+        assert!(requests[1].starts_with("GET /moved "));
+    }
+
+    #[test]
+    fn client_gives_up_after_too_many_redirects() {
+        let (listener, url) = listen("127.0.0.1");
+
+        let redirects = (0..=MAX_REDIRECTS)
+            .map(|_| response("302 Found", &[("Location", "/again")], ""))
+            .collect();
+        let server = serve(listener, redirects);
+
+        assert!(Client::new(is_allowed_in_tests).get(url.as_str()).is_err());
+        assert_eq!(server.join().unwrap().len(), MAX_REDIRECTS as usize + 1);
+    }
+
+    #[test]
+    fn source_linking_to_the_target_is_verified() {
+        let (listener, url) = listen("127.0.0.1");
+        let target = "https://chadtech.us/blog/post";
+
+        serve(
+            listener,
+            vec![
+                html("<p><a href=\"https://chadtech.us/blog/post\">A post</a></p>"),
+                html("<p><a href=\"https://chadtech.us/blog/other\">A post</a></p>"),
+                response("410 Gone", &[], ""),
+            ],
+        );
+
+        let client = Client::new(is_allowed_in_tests);
+
+        assert_eq!(links_to(&client, url.as_str(), target), Ok(true));
+        assert_eq!(links_to(&client, url.as_str(), target), Ok(false));
+        assert_eq!(links_to(&client, url.as_str(), target), Ok(false));
+    }
+
+    #[test]
+    fn source_that_can_not_be_read_right_now_is_an_error() {
+        let (listener, url) = listen("127.0.0.1");
+
+        serve(listener, vec![response("503 Service Unavailable", &[], "")]);
+
+        let client = Client::new(is_allowed_in_tests);
+
+        assert!(links_to(&client, url.as_str(), "https://chadtech.us/blog/post").is_err());
+    }
+
+    #[test]
+    fn endpoints_are_discovered_in_headers_before_html() {
+        let (listener, url) = listen("127.0.0.1");
+
+        serve(
+            listener,
+            vec![
+                response(
+                    "200 OK",
+                    &[
+                        ("Content-Type", "text/html"),
+                        ("Link", "</from-header>; rel=\"webmention\""),
+                    ],
+                    "<link rel=\"webmention\" href=\"/from-html\">",
+                ),
+                html("<link rel=\"me webmention\" href=\"/from-html\">"),
+                html("<a href=\"/no-endpoint\">Nothing</a>"),
+            ],
+        );
+
+        let client = Client::new(is_allowed_in_tests);
+        let endpoint = |client: &Client| {
+            discover(client, url.as_str())
+                .unwrap()
+                .map(|endpoint| endpoint.path().to_string())
+        };
+
+        assert_eq!(endpoint(&client), Some("/from-header".to_string()));
+        assert_eq!(endpoint(&client), Some("/from-html".to_string()));
+        assert_eq!(endpoint(&client), None);
+    }
+
+    #[test]
+    fn notify_posts_the_source_and_target_to_the_endpoint() {
+        let (listener, url) = listen("127.0.0.1");
+
+        let server = serve(
+            listener,
+            vec![
+                html("<link rel=\"webmention\" href=\"/webmention\">"),
+                response("202 Accepted", &[], ""),
+            ],
+        );
+
+        let client = Client::new(is_allowed_in_tests);
+        let source = "https://chadtech.us/blog/post";
+
+        assert_eq!(notify(&client, source, url.as_str()), Ok(()));
+
+        let requests = server.join().unwrap();
+        let endpoint_request = requests[1].as_str();
+
+        assert!(endpoint_request.starts_with("POST /webmention "));
+        assert!(endpoint_request.contains("source=https%3A%2F%2Fchadtech.us%2Fblog%2Fpost"));
+        assert!(endpoint_request.contains("target=http%3A%2F%2F127.0.0.1"));
+    }
+
+    #[test]
+    fn private_and_local_addresses_are_not_public() {
+        let not_public = [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fc00::1",
+            "fd12:3456::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:10.0.0.1",
+        ];
+
+        for ip in not_public.iter() {
+            assert!(!is_public(ip.parse().unwrap()), "{} is not public", ip);
+        }
+
+        assert!(is_public(IpAddr::V4(Ipv4Addr::new(93, 184, 216, 34))));
+        assert!(is_public(IpAddr::V6(Ipv6Addr::new(
+            0x2606, 0x2800, 0x220, 0x1, 0, 0, 0, 0x1
+        ))));
+    }
+
+    #[test]
+    fn only_public_http_urls_are_fetchable() {
+        let fetchable = |url: &str| is_fetchable(&Url::parse(url).unwrap());
+
+        assert!(fetchable("https://example.com/post"));
+        assert!(!fetchable("ftp://example.com/post"));
+        assert!(!fetchable("http://localhost:8080/"));
+        assert!(!fetchable("http://[fe80::1]/"));
+        assert!(!fetchable("http://192.168.0.1/"));
+    }
+
+    #[test]
+    fn target_slug_is_a_post_on_this_site() {
+        let site_url = "https://chadtech.us";
+
+        assert_eq!(
+            target_slug(site_url, "https://chadtech.us/blog/post?utm=1#top"),
+            Some("post")
+        );
+        assert_eq!(
+            target_slug(site_url, "https://chadtech.us/blog/post/"),
+            Some("post")
+        );
+        assert_eq!(target_slug(site_url, "https://chadtech.us/blog/"), None);
+        assert_eq!(target_slug(site_url, "https://chadtech.us/blog/a/b"), None);
+        assert_eq!(target_slug(site_url, "https://example.com/blog/post"), None);
+    }
+
+    #[test]
+    fn link_headers_name_the_webmention_endpoint() {
+        assert_eq!(
+            endpoint_in_link_header(
+                "<https://a.com/x>; rel=me, <https://a.com/wm>; rel=\"webmention\""
+            ),
+            Some("https://a.com/wm")
+        );
+        assert_eq!(endpoint_in_link_header("<https://a.com/x>; rel=me"), None);
+    }
+
+    #[test]
+    fn full_queue_turns_reservations_away_until_one_is_given_back() {
+        let (sender, receiver) = channel::<Job>();
+        let queue = Queue {
+            sender,
+            waiting: Arc::new(AtomicUsize::new(0)),
+        };
+
+        let mut reservations: Vec<Reservation> =
+            (0..MAX_WAITING).filter_map(|_| queue.reserve()).collect();
+
+        assert_eq!(reservations.len(), MAX_WAITING);
+        assert!(queue.reserve().is_none());
+
+        reservations.pop();
+
+        assert!(queue.reserve().is_some());
+
+        reservations.pop().unwrap().push(Job::Verify(1));
+
+        assert!(matches!(receiver.try_recv(), Ok(Job::Verify(1))));
+        assert_eq!(queue.waiting.load(Ordering::SeqCst), MAX_WAITING - 1);
+    }
+}
@@ -7,6 +7,7 @@ module Api exposing
     , PendingRequestCount
     , Request
     , Response
+    , adminQuery
     , customHandle
     , errorToString
     , handle
@@ -184,6 +185,13 @@ query =
     Request << Graphql.Http.queryRequest graphUrl
 
 
+adminQuery : String -> SelectionSet value RootQuery -> Request value
+adminQuery adminPassword =
+    Graphql.Http.queryRequest graphUrl
+        >> Graphql.Http.withHeader "x-admin-password" adminPassword
+        >> Request
+
+
 send :
     { toZpr : CustomResponse Error value key -> zpr
     , req : Request value
@@ -2,7 +2,7 @@
 -- https://github.com/dillonkearns/elm-graphql
 
 
-module Api.Query exposing (blogpostsV2)
+module Api.Query exposing (allBlogpostsV2, blogpostsV2)
 
 import Api.InputObject
 import Api.Interface
@@ -19,7 +19,16 @@ import Graphql.SelectionSet exposing (SelectionSet)
 import Json.Decode as Decode exposing (Decoder)
 
 
-{-| List of all version 2 blog posts
+{-| List of all version 2 blog posts, whatever their status
+-}
+allBlogpostsV2 :
+    SelectionSet decodesTo Api.Object.Post
+    -> SelectionSet (List decodesTo) RootQuery
+allBlogpostsV2 object____ =
+    Object.selectionForCompositeField "allBlogpostsV2" [] object____ (identity >> Decode.list)
+
+
+{-| List of the published version 2 blog posts, newest first
 -}
 blogpostsV2 :
     SelectionSet decodesTo Api.Object.Post
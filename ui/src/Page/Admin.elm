@@ -169,7 +169,7 @@ loadPage : Route -> Modelka -> ( Modelka, Cmd Zpr )
 loadPage route modelka =
     case route of
         AdminRoute.Blog ->
-            Blog.load BlogLoaded modelka
+            Blog.load modelka.adminPassword BlogLoaded modelka
 
         AdminRoute.Analytics ->
             AnalyticsPage.load AnalyticsLoaded modelka
@@ -66,21 +66,22 @@ poca flags =
 
 
 load :
-    (Api.Response Flags key -> zpr)
+    String
+    -> (Api.Response Flags key -> zpr)
     -> HasApi modelka key
     -> ( HasApi modelka key, Cmd zpr )
-load toZpr modelka =
+load adminPassword toZpr modelka =
     let
         flagsRequest : Api.Request Flags
         flagsRequest =
-            Query.blogpostsV2
+            Query.allBlogpostsV2
                 (SS.map2 PostV2
                     PostSS.id
                     PostSS.title
                     |> SS.map Post__V2
                 )
                 |> SS.map Flags
-                |> Api.query
+                |> Api.adminQuery adminPassword
     in
     Api.send
         { toZpr = toZpr